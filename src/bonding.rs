@@ -0,0 +1,86 @@
+use rapier2d::prelude::{ImpulseJointHandle, RigidBodyHandle};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+pub enum BondKind {
+    /// Slack up to the bond distance, then holds the pair together.
+    #[default]
+    Rope,
+    /// Pulls the pair toward the bond distance.
+    Spring,
+}
+
+impl BondKind {
+
+    pub fn all() -> [BondKind; 2] {
+        return [BondKind::Rope, BondKind::Spring];
+    }
+
+    pub fn name(&self) -> &'static str {
+        return match self {
+            BondKind::Rope => "Rope",
+            BondKind::Spring => "Spring",
+        };
+    }
+}
+
+//?         [[[BOND_SETTINGS]]]
+/// Rule layer joining close particles with joints. Which pairs bond and how close they have to be
+/// is the bond distance matrix of `PhysicsTypes`.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BondSettings {
+    pub enabled: bool,
+    pub kind: BondKind,
+    /// Most bonds a single particle can have.
+    pub max_bonds: usize,
+    /// A bond breaks when the joint force goes above this, zero never breaks by force.
+    pub break_force: f32,
+    /// A bond breaks when stretched above this multiple of its bond distance.
+    pub break_stretch: f32,
+    pub stiffness: f32,
+    pub damping: f32,
+}
+
+impl Default for BondSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            kind: BondKind::Rope,
+            max_bonds: 2,
+            break_force: 2000.0,
+            break_stretch: 2.0,
+            stiffness: 50.0,
+            damping: 1.0,
+        }
+    }
+}
+
+//?         [[[BOND]]]
+/// Two bonded bodies. Rope bonds are rapier rope joints, spring bonds have no joint and are
+/// driven by forces added before every physics step.
+pub struct Bond {
+    pub body1: RigidBodyHandle,
+    pub body2: RigidBodyHandle,
+    pub distance: f32,
+    pub joint: Option<ImpulseJointHandle>,
+}
+
+impl Bond {
+
+    pub fn kind(&self) -> BondKind {
+        return match self.joint {
+            Some(_) => BondKind::Rope,
+            None => BondKind::Spring,
+        };
+    }
+}
+
+//?         [[[BOND_STATS]]]
+/// Current number of bonds and bonds formed and broken since the world was created.
+#[derive(Clone, Copy, Default)]
+pub struct BondStats {
+    pub count: usize,
+    pub formed: usize,
+    pub broken: usize,
+}
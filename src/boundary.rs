@@ -0,0 +1,126 @@
+use macroquad::prelude::*;
+use rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::util::*;
+
+//?         [[[BOUNDARY]]]
+/// Shape of the world edge. `size` passed to the methods is `Settings::world_radius`, which is the
+/// diameter of the circular worlds and the side length of the square ones.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Boundary {
+    /// Soft circular wall, pushes back proportionally to the penetration depth.
+    SoftCircle { stiffness: f32 },
+    /// Hard circular wall, particles are put back on the edge and bounce with `stiffness` restitution.
+    HardCircle { stiffness: f32 },
+    /// Soft square walls.
+    Box { stiffness: f32 },
+    /// Periodic square world, particles leaving on one side come back on the other.
+    Torus,
+    /// No boundary at all.
+    Open,
+}
+
+impl Default for Boundary {
+    fn default() -> Self {
+        Self::SoftCircle { stiffness: 0.2 }
+    }
+}
+
+impl Boundary {
+
+    pub fn all() -> [Boundary; 5] {
+        return [
+            Boundary::SoftCircle { stiffness: 0.2 },
+            Boundary::HardCircle { stiffness: 0.5 },
+            Boundary::Box { stiffness: 0.2 },
+            Boundary::Torus,
+            Boundary::Open,
+        ];
+    }
+
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Boundary::SoftCircle { .. } => "Soft Circle",
+            Boundary::HardCircle { .. } => "Hard Circle",
+            Boundary::Box { .. } => "Box",
+            Boundary::Torus => "Torus",
+            Boundary::Open => "Open",
+        };
+    }
+
+    /// Short name taken by the `--boundary` command line argument.
+    pub fn cli_name(&self) -> &'static str {
+        return match self {
+            Boundary::SoftCircle { .. } => "soft",
+            Boundary::HardCircle { .. } => "hard",
+            Boundary::Box { .. } => "box",
+            Boundary::Torus => "torus",
+            Boundary::Open => "open",
+        };
+    }
+
+    pub fn same_kind(&self, other: &Boundary) -> bool {
+        return std::mem::discriminant(self) == std::mem::discriminant(other);
+    }
+
+    /// Side length of the periodic cell for the torus, used by the neighbor search to wrap around the seam.
+    pub fn period(&self, size: f32) -> Option<f32> {
+        return match self {
+            Boundary::Torus => Some(size),
+            _ => None,
+        };
+    }
+
+    pub fn constrain(&self, body: &mut RigidBody, size: f32) {
+        let half = size / 2.0;
+        let pos = matrix_to_vec2(body.position().translation);
+        match *self {
+            Boundary::SoftCircle { stiffness } => {
+                let dist_from_center = pos.length();
+                if dist_from_center >= half {
+                    let hold_force = pos.normalize_or_zero() * (half - dist_from_center) * stiffness;
+                    body.apply_impulse(vector![hold_force.x, hold_force.y], true);
+                }
+            },
+            Boundary::HardCircle { stiffness } => {
+                let dist_from_center = pos.length();
+                if dist_from_center > half {
+                    let normal = pos.normalize_or_zero();
+                    let edge = normal * half;
+                    body.set_translation(vector![edge.x, edge.y], true);
+                    let vel = Vec2::new(body.linvel().x, body.linvel().y);
+                    let outward = vel.dot(normal);
+                    if outward > 0.0 {
+                        let bounced = vel - normal * outward * (1.0 + stiffness);
+                        body.set_linvel(vector![bounced.x, bounced.y], true);
+                    }
+                }
+            },
+            Boundary::Box { stiffness } => {
+                let inside = pos.clamp(Vec2::splat(-half), Vec2::splat(half));
+                if inside != pos {
+                    let hold_force = (inside - pos) * stiffness;
+                    body.apply_impulse(vector![hold_force.x, hold_force.y], true);
+                }
+            },
+            Boundary::Torus => {
+                let wrapped = wrap_around(pos, size);
+                if wrapped != pos {
+                    body.set_translation(vector![wrapped.x, wrapped.y], true);
+                }
+            },
+            Boundary::Open => {},
+        }
+    }
+
+    pub fn draw(&self, size: f32) {
+        let half = size / 2.0;
+        match self {
+            Boundary::SoftCircle { .. } => draw_smooth_circle(half, Vec2::ZERO, 32., 1.0, LIGHTGRAY),
+            Boundary::HardCircle { .. } => draw_smooth_circle(half, Vec2::ZERO, 32., 3.0, WHITE),
+            Boundary::Box { .. } => draw_rectangle_lines(-half, -half, size, size, 2.0, LIGHTGRAY),
+            Boundary::Torus => draw_rectangle_lines(-half, -half, size, size, 1.0, DARKGRAY),
+            Boundary::Open => {},
+        }
+    }
+}
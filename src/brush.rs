@@ -0,0 +1,77 @@
+use macroquad::prelude::*;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum BrushTool {
+    Select,
+    Spawn,
+    Erase,
+    Attract,
+    Repel,
+    Kick,
+}
+
+impl BrushTool {
+
+    pub fn all() -> [BrushTool; 6] {
+        return [BrushTool::Select, BrushTool::Spawn, BrushTool::Erase, BrushTool::Attract, BrushTool::Repel, BrushTool::Kick];
+    }
+
+    pub fn name(&self) -> &'static str {
+        return match self {
+            BrushTool::Select => "Select",
+            BrushTool::Spawn => "Spawn",
+            BrushTool::Erase => "Erase",
+            BrushTool::Attract => "Attract",
+            BrushTool::Repel => "Repel",
+            BrushTool::Kick => "Kick",
+        };
+    }
+
+    pub fn color(&self) -> Color {
+        return match self {
+            BrushTool::Select => WHITE,
+            BrushTool::Spawn => GREEN,
+            BrushTool::Erase => RED,
+            BrushTool::Attract => SKYBLUE,
+            BrushTool::Repel => ORANGE,
+            BrushTool::Kick => YELLOW,
+        };
+    }
+}
+
+//?         [[[BRUSH]]]
+/// Mouse tool state. `strength` is the impulse for attract/repel/kick and
+/// a tenth of the number of particles spawned per frame for the spawn brush.
+pub struct Brush {
+    pub tool: BrushTool,
+    pub radius: f32,
+    pub strength: f32,
+    pub spawn_type: usize,
+}
+
+impl Default for Brush {
+    fn default() -> Self {
+        Self {
+            tool: BrushTool::Select,
+            radius: 50.0,
+            strength: 10.0,
+            spawn_type: 0,
+        }
+    }
+}
+
+impl Brush {
+
+    pub fn spawn_count(&self) -> usize {
+        return (self.strength / 10.0).ceil().max(1.0) as usize;
+    }
+
+    pub fn draw(&self, center: Vec2) {
+        if self.tool == BrushTool::Select {
+            return;
+        }
+        let color = self.tool.color();
+        draw_circle_lines(center.x, center.y, self.radius, 1.0, color);
+        draw_circle(center.x, center.y, self.radius, Color::new(color.r, color.g, color.b, 0.08));
+    }
+}
@@ -1,12 +1,17 @@
 use crate::globals::*;
 use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub const MIN_ZOOM: f32 = 0.05;
+pub const MAX_ZOOM: f32 = 50.0;
+/// Zoom factor of a single wheel notch or +/- key press.
+const ZOOM_STEP: f32 = 1.2;
+/// How fast zoom and follow ease toward their targets, per second.
+const EASING: f32 = 10.0;
 
 pub fn create_camera() -> Camera2D {
     let mut camera2d = Camera2D {
-        zoom: Vec2 {
-            x: ZOOM_RATE,
-            y: SCREEN_RATIO * ZOOM_RATE,
-        },
+        zoom: aspect_zoom(1.0),
         target: Vec2 {
             x: 0.0,
             y: 0.0,
@@ -17,33 +22,143 @@ pub fn create_camera() -> Camera2D {
     return camera2d;
 }
 
-pub fn control_camera(camera: &mut Camera2D /* , screen_ratio: f32 */) {
-    if is_key_pressed(KeyCode::KpAdd) {
-        camera.zoom += Vec2::new(ZOOM_RATE * 0.1, SCREEN_RATIO * ZOOM_RATE * 0.1);
-    }
-    if is_key_pressed(KeyCode::KpSubtract) {
-        if camera.zoom.x > 0.0001 {
-            camera.zoom -= Vec2::new(ZOOM_RATE * 0.1, SCREEN_RATIO * ZOOM_RATE * 0.1);
-        }
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+pub enum Follow {
+    #[default]
+    Off,
+    /// Keeps the selected particle centered.
+    Particle,
+    /// Keeps the center of the cluster of the selected particle centered.
+    Cluster,
+}
+
+impl Follow {
+
+    pub fn all() -> [Follow; 3] {
+        return [Follow::Off, Follow::Particle, Follow::Cluster];
     }
-    if is_key_pressed(KeyCode::KpMultiply) {
-        camera.zoom = Vec2::new(ZOOM_RATE, SCREEN_RATIO * ZOOM_RATE);
-        camera.target = Vec2::ZERO;
+
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Follow::Off => "Off",
+            Follow::Particle => "Selected Particle",
+            Follow::Cluster => "Selected Cluster",
+        };
     }
-    if is_key_pressed(KeyCode::KpEnter) {
-        //camera.zoom = Vec2::new(ZOOM_RATE, SCREEN_RATIO * ZOOM_RATE);
-        camera.target = Vec2::ZERO;
+}
+
+//?         [[[CAMERA_CONTROL]]]
+/// Mouse and keyboard camera: right/middle drag pans, the wheel zooms at the cursor,
+/// keypad or +/- zoom at the screen center, arrows pan, `*` resets the view.
+pub struct CameraControl {
+    /// Current zoom relative to the default view.
+    zoom: f32,
+    target_zoom: f32,
+    /// Screen point which stays over the same world point while zooming.
+    zoom_anchor: Option<Vec2>,
+    drag_from: Option<Vec2>,
+}
+
+impl CameraControl {
+
+    pub fn new() -> Self {
+        Self { zoom: 1.0, target_zoom: 1.0, zoom_anchor: None, drag_from: None }
     }
-    if is_key_pressed(KeyCode::Left) {
-        camera.target.x -= 100.0;
+
+    /// `follow` is the world position to keep centered, if any. Mouse input is ignored while the pointer is over the UI,
+    /// keys while a UI text field has the keyboard focus.
+    pub fn update(&mut self, camera: &mut Camera2D, pointer_over_ui: bool, ui_wants_keyboard: bool, follow: Option<Vec2>) {
+        let dt = get_frame_time();
+        let mouse = Vec2::from(mouse_position());
+        if !pointer_over_ui {
+            let (_, wheel) = mouse_wheel();
+            if wheel != 0.0 {
+                self.zoom_by(ZOOM_STEP.powf(wheel.signum()), Some(mouse));
+            }
+        }
+        let dragging = is_mouse_button_down(MouseButton::Right) || is_mouse_button_down(MouseButton::Middle);
+        match self.drag_from {
+            Some(from) if dragging => {
+                camera.target += camera.screen_to_world(from) - camera.screen_to_world(mouse);
+                self.drag_from = Some(mouse);
+            },
+            None if dragging && !pointer_over_ui => self.drag_from = Some(mouse),
+            _ if !dragging => self.drag_from = None,
+            _ => {},
+        }
+        if !ui_wants_keyboard {
+            self.keyboard(camera);
+        }
+        let ease = 1.0 - (-EASING * dt).exp();
+        if (self.target_zoom - self.zoom).abs() > self.zoom * 0.001 {
+            let anchor = match follow {
+                Some(_) => None,
+                None => self.zoom_anchor,
+            };
+            let before = anchor.map(|a| camera.screen_to_world(a));
+            self.zoom += (self.target_zoom - self.zoom) * ease;
+            self.apply_zoom(camera);
+            if let (Some(a), Some(before)) = (anchor, before) {
+                camera.target += before - camera.screen_to_world(a);
+            }
+        } else {
+            self.zoom = self.target_zoom;
+            self.apply_zoom(camera);
+        }
+        if let Some(pos) = follow {
+            camera.target += (pos - camera.target) * ease;
+        }
     }
-    if is_key_pressed(KeyCode::Right) {
-        camera.target.x += 100.0;
+
+    fn zoom_by(&mut self, factor: f32, anchor: Option<Vec2>) {
+        self.target_zoom = (self.target_zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.zoom_anchor = anchor;
     }
-    if is_key_pressed(KeyCode::Up) {
-        camera.target.y += 100.0;
+
+    fn apply_zoom(&self, camera: &mut Camera2D) {
+        camera.zoom = aspect_zoom(self.zoom);
     }
-    if is_key_pressed(KeyCode::Down) {
-        camera.target.y -= 100.0;
+
+    fn keyboard(&mut self, camera: &mut Camera2D) {
+        if is_key_pressed(KeyCode::KpAdd) || is_key_pressed(KeyCode::Equal) {
+            self.zoom_by(ZOOM_STEP, None);
+        }
+        if is_key_pressed(KeyCode::KpSubtract) || is_key_pressed(KeyCode::Minus) {
+            self.zoom_by(1.0 / ZOOM_STEP, None);
+        }
+        if is_key_pressed(KeyCode::KpMultiply) {
+            self.target_zoom = 1.0;
+            self.zoom_anchor = None;
+            camera.target = Vec2::ZERO;
+        }
+        if is_key_pressed(KeyCode::KpEnter) {
+            camera.target = Vec2::ZERO;
+        }
+        let step = 100.0 / self.zoom;
+        if is_key_pressed(KeyCode::Left) {
+            camera.target.x -= step;
+        }
+        if is_key_pressed(KeyCode::Right) {
+            camera.target.x += step;
+        }
+        if is_key_pressed(KeyCode::Up) {
+            camera.target.y += step;
+        }
+        if is_key_pressed(KeyCode::Down) {
+            camera.target.y -= step;
+        }
     }
 }
+
+/// Camera zoom for the current window size. The shorter window side always spans the same part
+/// of the world, so nothing stretches when the window is resized.
+pub fn aspect_zoom(zoom: f32) -> Vec2 {
+    let (w, h) = (screen_width().max(1.0), screen_height().max(1.0));
+    let short = w.min(h);
+    return Vec2::new(ZOOM_RATE * zoom * short / w, ZOOM_RATE * zoom * short / h);
+}
+
+/// Converts a distance in screen pixels to world units at the current zoom.
+pub fn screen_to_world_distance(camera: &Camera2D, pixels: f32) -> f32 {
+    return pixels / (camera.zoom.x.abs() * screen_width() / 2.0);
+}
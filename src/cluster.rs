@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+use macroquad::color::hsl_to_rgb;
+use macroquad::prelude::*;
+use crate::element::*;
+use crate::globals::*;
+use crate::spatial_hash::*;
+
+//?         [[[CLUSTER]]]
+/// One connected group of particles.
+#[derive(Clone)]
+pub struct Cluster {
+    pub id: usize,
+    pub size: usize,
+    pub center: Vec2,
+    /// Number of members of every physics type.
+    pub composition: Vec<usize>,
+}
+
+//?         [[[CLUSTER_REPORT]]]
+#[derive(Clone, Default)]
+pub struct ClusterReport {
+    pub sim_time: f64,
+    /// Clusters with at least `min_size` members, largest first.
+    pub clusters: Vec<Cluster>,
+    /// Particles in groups smaller than `min_size`.
+    pub loose: usize,
+}
+
+impl ClusterReport {
+
+    pub fn largest(&self) -> usize {
+        return self.clusters.first().map(|c| c.size).unwrap_or(0);
+    }
+
+    pub fn mean_size(&self) -> f32 {
+        if self.clusters.is_empty() {
+            return 0.0;
+        }
+        return self.clusters.iter().map(|c| c.size).sum::<usize>() as f32 / self.clusters.len() as f32;
+    }
+
+    /// Cluster counts in size buckets `[from, to]`, the last bucket is open ended.
+    pub fn size_histogram(&self, bounds: &[usize]) -> Vec<usize> {
+        let mut histogram = vec![0; bounds.len()];
+        for cluster in self.clusters.iter() {
+            if let Some(i) = bounds.iter().rposition(|&b| cluster.size >= b) {
+                histogram[i] += 1;
+            }
+        }
+        return histogram;
+    }
+}
+
+//?         [[[CLUSTER_ANALYSIS]]]
+/// Connected components of the "closer than `distance`" graph over particle centers,
+/// recomputed every `every` steps.
+pub struct ClusterAnalysis {
+    pub enabled: bool,
+    pub distance: f32,
+    pub every: u32,
+    pub min_size: usize,
+    pub color_by_cluster: bool,
+    steps: u32,
+    grid: SpatialHash,
+    labels: HashMap<u64, usize>,
+    pub report: ClusterReport,
+}
+
+impl Default for ClusterAnalysis {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            distance: 20.0,
+            every: 30,
+            min_size: 3,
+            color_by_cluster: false,
+            steps: 0,
+            grid: SpatialHash::new(),
+            labels: HashMap::new(),
+            report: ClusterReport::default(),
+        }
+    }
+}
+
+impl ClusterAnalysis {
+
+    pub fn with_min_size(min_size: usize) -> Self {
+        return Self { min_size, ..Default::default() };
+    }
+
+    pub fn update(&mut self, elements: &ElementCollector, types_num: usize, sim_time: f64) {
+        if !self.enabled {
+            return;
+        }
+        if self.steps.is_multiple_of(self.every.max(1)) {
+            self.analyze(elements, types_num, sim_time);
+        }
+        self.steps += 1;
+    }
+
+    pub fn analyze(&mut self, elements: &ElementCollector, types_num: usize, sim_time: f64) {
+        let settings = get_settings();
+        let keys: Vec<u64> = elements.get_iter().map(|(key, _)| *key).collect();
+        let entries = elements.get_iter().map(|(_, element)| NeighborEntry {
+            handle: element.get_rigid_handle(),
+            pos: element.pos,
+            p_type: element.physics_type,
+        });
+        self.grid.rebuild(self.distance, settings.boundary.period(settings.world_radius), entries);
+        let groups = connected_groups(&self.grid, self.distance);
+        let mut report = ClusterReport { sim_time, ..Default::default() };
+        self.labels.clear();
+        for members in groups {
+            if members.len() < self.min_size.max(1) {
+                report.loose += members.len();
+                continue;
+            }
+            let id = report.clusters.len();
+            let mut composition = vec![0; types_num];
+            let mut center = Vec2::ZERO;
+            for &i in members.iter() {
+                let entry = &self.grid.entries()[i];
+                if let Some(n) = composition.get_mut(entry.p_type as usize) {
+                    *n += 1;
+                }
+                center += entry.pos;
+                self.labels.insert(keys[i], id);
+            }
+            center /= members.len() as f32;
+            report.clusters.push(Cluster { id, size: members.len(), center, composition });
+        }
+        self.report = report;
+    }
+
+    pub fn clear(&mut self) {
+        self.labels.clear();
+        self.report = ClusterReport::default();
+        self.steps = 0;
+    }
+
+    pub fn cluster_of(&self, key: u64) -> Option<usize> {
+        return self.labels.get(&key).copied();
+    }
+
+    /// Color of a particle when coloring by cluster, loose particles are dimmed.
+    pub fn color_of(&self, key: u64) -> Color {
+        return match self.cluster_of(key) {
+            Some(id) => cluster_color(id),
+            None => Color::new(0.3, 0.3, 0.3, 1.0),
+        };
+    }
+}
+
+/// Golden ratio hue steps keep neighboring ids visually apart.
+pub fn cluster_color(id: usize) -> Color {
+    let hue = (id as f32 * 0.618034) % 1.0;
+    return hsl_to_rgb(hue, 0.9, 0.6);
+}
+
+/// Indices into `grid.entries()` of every connected group, largest first (ties by lowest index).
+fn connected_groups(grid: &SpatialHash, distance: f32) -> Vec<Vec<usize>> {
+    let index: HashMap<_, usize> = grid.entries().iter().enumerate().map(|(i, e)| (e.handle, i)).collect();
+    let mut parents: Vec<usize> = (0..grid.len()).collect();
+    for (i, entry) in grid.entries().iter().enumerate() {
+        grid.for_each_in_range(entry.pos, distance, |other| {
+            let j = index[&other.handle];
+            if j > i {
+                union(&mut parents, i, j);
+            }
+        });
+    }
+    let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..grid.len() {
+        let root = find(&mut parents, i);
+        groups.entry(root).or_default().push(i);
+    }
+    let mut groups: Vec<Vec<usize>> = groups.into_values().collect();
+    groups.sort_by(|a, b| b.len().cmp(&a.len()).then(a[0].cmp(&b[0])));
+    return groups;
+}
+
+fn find(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    return i;
+}
+
+fn union(parents: &mut [usize], a: usize, b: usize) {
+    let (ra, rb) = (find(parents, a), find(parents, b));
+    if ra != rb {
+        parents[ra.max(rb)] = ra.min(rb);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rapier2d::prelude::RigidBodyHandle;
+    use super::*;
+
+    fn grid(period: Option<f32>, distance: f32, points: &[(f32, f32)]) -> SpatialHash {
+        let mut grid = SpatialHash::new();
+        let entries = points.iter().enumerate().map(|(i, &(x, y))| NeighborEntry {
+            handle: RigidBodyHandle::from_raw_parts(i as u32, 0),
+            pos: Vec2::new(x, y),
+            p_type: 0,
+        });
+        grid.rebuild(distance, period, entries);
+        return grid;
+    }
+
+    #[test]
+    fn union_find_joins_transitively() {
+        let mut parents: Vec<usize> = (0..6).collect();
+        union(&mut parents, 4, 5);
+        union(&mut parents, 1, 4);
+        union(&mut parents, 2, 3);
+        assert_eq!(find(&mut parents, 5), find(&mut parents, 1));
+        assert_eq!(find(&mut parents, 3), find(&mut parents, 2));
+        assert_ne!(find(&mut parents, 1), find(&mut parents, 2));
+        assert_eq!(find(&mut parents, 0), 0);
+    }
+
+    #[test]
+    fn chains_become_one_group() {
+        // 0-1-2 chained 8 apart, 3-4 a separate pair, 5 alone
+        let points = [(0.0, 0.0), (8.0, 0.0), (16.0, 0.0), (100.0, 100.0), (100.0, 109.0), (-200.0, 0.0)];
+        let groups = connected_groups(&grid(None, 10.0, &points), 10.0);
+        assert_eq!(groups, vec![vec![0, 1, 2], vec![3, 4], vec![5]]);
+    }
+
+    #[test]
+    fn groups_join_across_the_seam() {
+        let points = [(48.0, 0.0), (-48.0, 0.0), (0.0, 47.0), (0.0, -47.0), (0.0, 0.0)];
+        let groups = connected_groups(&grid(Some(100.0), 10.0, &points), 10.0);
+        assert_eq!(groups, vec![vec![0, 1], vec![2, 3], vec![4]]);
+        let groups = connected_groups(&grid(None, 10.0, &points), 10.0);
+        assert_eq!(groups.len(), 5);
+    }
+}
@@ -1,13 +1,15 @@
 #![allow(unused)]
-use std::collections::hash_map::{Iter, IterMut};
-use std::collections::HashMap;
-//use std::f32::consts::PI;
+use std::collections::btree_map::{Iter, IterMut};
+use std::collections::{BTreeMap, HashMap};
+use std::f32::consts::PI;
 
 use crate::globals::*;
 use crate::timer::Timer;
 use crate::util::*;
 use crate::physics::*;
 use crate::physics_types::*;
+use crate::snapshot::*;
+use crate::spatial_hash::NeighborSearch;
 use macroquad::{color, prelude::*};
 use macroquad::rand::*;
 use rapier2d::geometry::*;
@@ -37,9 +39,25 @@ impl Reactive for Element {
 
 }
 
+//?         [[[ELEMENT_INFO]]]
+/// Read-only view of a single element for the inspector window.
+pub struct ElementInfo {
+    pub key: u64,
+    pub physics_type: u128,
+    pub color: Color,
+    pub pos: Vec2,
+    pub vel: Vec2,
+    pub kin_eng: f32,
+    pub mass: f32,
+    pub neighbors: usize,
+    pub actions: Vec<f32>,
+}
+
 pub struct Element {
     pub key: u64,
     pub pos: Vec2,
+    /// Position drawn this frame, interpolated between the last two physics states.
+    render_pos: Vec2,
     pub rot: f32,
     pub shape: SharedShape,
     stroke_color: Option<Color>,
@@ -63,7 +81,7 @@ impl Physical for Element {
         //    PINK, VIOLET, DARKBLUE, WHITE, SKYBLUE, LIME, DARKPURPLE, 
         //    BROWN, DARKBROWN, DARKGRAY, LIGHTGRAY, 
         //];
-        let key = gen_range(u64::MIN, u64::MAX);
+        let key = random_key();
         let t = rand::gen_range(0, types_num);
         /* let t: usize = match fix_type {
             None => rand::gen_range(0, types_num),
@@ -77,6 +95,7 @@ impl Physical for Element {
         Self {
             key,
             pos: position,
+            render_pos: position,
             rot: 0.0,
             shape: shape.clone(),
             stroke_color: stroke,
@@ -113,7 +132,9 @@ impl Physical for Element {
                 warn!("can't find rigid body!");
             }
         }
-        self.react(physics);
+        if get_settings().neighbor_search == NeighborSearch::Rapier {
+            self.react(physics);
+        }
         /* self.timer += get_frame_time();
         if self.timer >= PRECISION {
             self.timer -= PRECISION;
@@ -136,7 +157,7 @@ impl Physical for Element {
         }
         if settings.field_range {
             let r = physics.get_physics_type(self.physics_type).get_field_range() * settings.field;
-            draw_circle_lines(self.pos.x, self.pos.y, r, 0.1, LIGHTGRAY);
+            draw_circle_lines(self.render_pos.x, self.render_pos.y, r, 0.1, LIGHTGRAY);
         }
         
     }
@@ -165,17 +186,22 @@ impl Physical for Element {
 impl Element {
 
     fn draw_circle_object(&self) {
-        let x0 = self.pos.x;
-        let y0 = self.pos.y;
+        let x0 = self.render_pos.x;
+        let y0 = self.render_pos.y;
         let size = self.size;
         let rot_vec = Vec2::from_angle(self.rot);
         let rv = rot_vec*size*0.5;
         draw_circle(x0, y0, size*2.0, self.fill_color);
     }
 
+    /// Filled circle in an arbitrary color, e.g. the color of the cluster the element belongs to.
+    pub fn draw_colored(&self, color: Color) {
+        draw_circle(self.render_pos.x, self.render_pos.y, self.size*2.0, color);
+    }
+
     fn draw_circle_stroke_object(&self) {
-        let x0 = self.pos.x;
-        let y0 = self.pos.y;
+        let x0 = self.render_pos.x;
+        let y0 = self.render_pos.y;
         let size = self.size;
         let rot_vec = Vec2::from_angle(self.rot);
         let rv = rot_vec*size*0.5;
@@ -183,8 +209,8 @@ impl Element {
     }
 
     fn draw_circle_energy(&self) {
-        let x0 = self.pos.x;
-        let y0 = self.pos.y;
+        let x0 = self.render_pos.x;
+        let y0 = self.render_pos.y;
         let eng = (self.energy/500.0).log10()-1.0;
         let r = clamp(0.2 + eng, 0.0, 1.0);
         let color = Color::new(r, 0.2, 0.2, 1.0);
@@ -206,15 +232,91 @@ impl Element {
 
     fn out_of_edges(&mut self, body: &mut RigidBody) {
         let settings = get_settings();
-        let r = settings.world_radius;
-        let mut raw_pos = matrix_to_vec2(body.position().translation);
-        let dist_from_center = Vec2::ZERO.distance(raw_pos).abs();
-        let dir = (raw_pos).normalize_or_zero();
-        if dist_from_center >= r/2. {
-            let hold_force = dir * (r/2.0 - dist_from_center)/5.0;
-            let hf = vector![hold_force.x, hold_force.y];
-            body.apply_impulse(hf, true) 
+        settings.boundary.constrain(body, settings.world_radius);
+    }
+
+    pub fn info(&self, physics: &Physics) -> ElementInfo {
+        let physics_data = physics.get_physics_data(self.rigid_handle);
+        let (vel, _) = physics.get_object_velocity(self.rigid_handle).unwrap_or((Vec2::ZERO, 0.0));
+        let p_type = physics.get_physics_type(self.physics_type);
+        let actions = (0..physics.types.types_num()).map(|to| p_type.get_action(to)).collect();
+        ElementInfo {
+            key: self.key,
+            physics_type: self.physics_type,
+            color: self.fill_color,
+            pos: self.pos,
+            vel,
+            kin_eng: physics_data.kin_eng.unwrap_or(0.0),
+            mass: physics_data.mass,
+            neighbors: physics.count_neighbors(self.rigid_handle, self.pos, self.physics_type),
+            actions,
+        }
+    }
+
+    pub fn get_rigid_handle(&self) -> RigidBodyHandle {
+        return self.rigid_handle;
+    }
+
+    pub fn draw_selection(&self, phase: f32) {
+        let r = self.size * 2.0 + 4.0 + 2.0 * phase.sin();
+        draw_circle_lines(self.render_pos.x, self.render_pos.y, r, 1.0, WHITE);
+    }
+
+    /// State read from the rigid body itself, `pos` and `rot` lag one physics step behind it.
+    pub fn to_state(&self, physics: &Physics) -> ElementState {
+        let physics_data = physics.get_physics_data(self.rigid_handle);
+        let (lin_vel, ang_vel) = physics.get_object_velocity(self.rigid_handle).unwrap_or((Vec2::ZERO, 0.0));
+        let density = physics.rigid_bodies.get(self.rigid_handle)
+            .and_then(|body| body.colliders().first())
+            .and_then(|collider| physics.colliders.get(*collider))
+            .map(|collider| collider.density());
+        ElementState {
+            key: self.key,
+            physics_type: self.physics_type as usize,
+            pos: [physics_data.position.x, physics_data.position.y],
+            rot: physics_data.rotation,
+            lin_vel: [lin_vel.x, lin_vel.y],
+            ang_vel,
+            size: self.size,
+            density,
+        }
+    }
+
+    pub fn from_state(state: &ElementState, physics: &mut Physics) -> Self {
+        let settings = get_settings();
+        let p_type = state.physics_type as u128;
+        let position = Vec2::new(state.pos[0], state.pos[1]);
+        let shape = SharedShape::ball(state.size);
+        let color = physics.get_physics_type(p_type).get_color();
+        // rapier reports rotation shifted by PI (see Physics::iso_to_vec2_rot)
+        let rbh = Self::add_to_physic_space(&position, state.rot - PI, shape.clone(), false, settings.damping, physics, p_type);
+        physics.set_object_velocity(rbh, Vec2::new(state.lin_vel[0], state.lin_vel[1]), state.ang_vel);
+        let mut element = Self {
+            key: state.key,
+            pos: position,
+            render_pos: position,
+            rot: state.rot,
+            shape: shape.clone(),
+            stroke_color: Some(BLUE),
+            fill_color: color,
+            rigid_handle: rbh,
+            joint: None,
+            physics_type: p_type,
+            timer: 0.0,
+            energy: 0.0,
+            size: state.size,
         };
+        element.set_size(state.size, state.density.unwrap_or(settings.particle_dense), physics);
+        return element;
+    }
+
+    pub fn set_physics_type(&mut self, p_type: u128, color: Color, physics: &mut Physics) {
+        self.physics_type = p_type;
+        self.fill_color = color;
+        if let Some(rb) = physics.rigid_bodies.get_mut(self.rigid_handle) {
+            rb.user_data = p_type;
+        }
+        physics.refresh_collider(self.rigid_handle);
     }
 
     pub fn set_damping(&mut self, damping: f32, physics: &mut Physics) {
@@ -239,20 +341,23 @@ impl Element {
 
 
 
+/// Elements are kept ordered by key, so every pass over them (and thus the whole run) is reproducible for a given seed.
 pub struct ElementCollector {
-    pub elements: HashMap<u64, Element>,
+    pub elements: BTreeMap<u64, Element>,
 }
 
 impl ElementCollector {
     pub fn new() -> Self {
         Self {
-            elements: HashMap::new(),
+            elements: BTreeMap::new(),
         }
     }
 
     pub fn add_many_elements(&mut self, elements_num: usize, physics: &mut Physics) {
-        for _ in 0..elements_num {
-            _ = self.add_element(None, GREEN, None, false, physics);
+        let settings = get_settings();
+        for i in 0..elements_num {
+            let position = settings.layout.position(i, settings.world_radius);
+            _ = self.add_element(Some(position), GREEN, None, false, physics);
         }
     }
 
@@ -289,6 +394,117 @@ impl ElementCollector {
         return (key, rbh);
     }
 
+    /// Syncs every element with its body, then (for the grid search) computes all field forces in one batch.
+    pub fn update_all(&mut self, physics: &mut Physics) {
+        let settings = get_settings();
+        for (_, elem) in self.elements.iter_mut() {
+            elem.update(physics);
+        }
+        self.apply_reactions(physics);
+        if settings.neighbor_search == NeighborSearch::Grid {
+            physics.update_field_forces(&settings);
+        }
+    }
+
+    /// Changes the type and color of the elements which reacted this step.
+    fn apply_reactions(&mut self, physics: &mut Physics) {
+        let changes = physics.update_reactions();
+        if changes.is_empty() {
+            return;
+        }
+        let changes: HashMap<RigidBodyHandle, u128> = changes.into_iter().collect();
+        for (_, particle) in self.elements.iter_mut() {
+            if let Some(p_type) = changes.get(&particle.rigid_handle) {
+                let color = physics.get_physics_type(*p_type).get_color();
+                particle.set_physics_type(*p_type, color, physics);
+            }
+        }
+        // forces of this step already see the new types
+        physics.update_neighbors();
+    }
+
+    /// Re-applies particle types and colors after the physics types were replaced.
+    pub fn apply_types(&mut self, physics: &mut Physics) {
+        let types_num = physics.types.types_num() as u128;
+        for (_, particle) in self.elements.iter_mut() {
+            let p_type = particle.physics_type % types_num;
+            let color = physics.get_physics_type(p_type).get_color();
+            particle.set_physics_type(p_type, color, physics);
+        }
+        physics.update_neighbors();
+    }
+
+    /// Sets the drawn position of every element between `pos` (read at the start of the last physics step)
+    /// and the current body position. Jumps across a periodic seam are not interpolated.
+    pub fn interpolate(&mut self, physics: &Physics, alpha: f32) {
+        let max_jump = get_settings().world_radius / 2.0;
+        for (_, element) in self.elements.iter_mut() {
+            let current = match physics.rigid_bodies.get(element.rigid_handle) {
+                Some(body) => matrix_to_vec2(body.position().translation),
+                None => element.pos,
+            };
+            element.render_pos = if current.distance(element.pos) > max_jump { current } else { element.pos.lerp(current, alpha) };
+        }
+    }
+
+    /// Lines between bonded elements at their drawn positions, colored by both ends. Bonds across
+    /// a periodic seam are skipped.
+    pub fn draw_bonds(&self, physics: &Physics) {
+        let bonds = physics.bonded_pairs();
+        if bonds.is_empty() {
+            return;
+        }
+        let max_jump = get_settings().world_radius / 2.0;
+        let ends: HashMap<RigidBodyHandle, (Vec2, Color, f32)> = self.elements.values()
+            .map(|element| (element.rigid_handle, (element.render_pos, element.fill_color, element.size)))
+            .collect();
+        for (handle1, handle2) in bonds {
+            if let (Some((pos1, color1, size)), Some((pos2, color2, _))) = (ends.get(&handle1), ends.get(&handle2)) {
+                if pos1.distance(*pos2) > max_jump {
+                    continue;
+                }
+                let color = Color::new((color1.r + color2.r) / 2.0, (color1.g + color2.g) / 2.0, (color1.b + color2.b) / 2.0, 0.8);
+                draw_line(pos1.x, pos1.y, pos2.x, pos2.y, size * 0.6, color);
+            }
+        }
+    }
+
+    /// Mean linear speed of the elements of every physics type, zero for types with no elements.
+    pub fn type_speeds(&self, physics: &Physics) -> Vec<f32> {
+        let types_num = physics.types.types_num();
+        let mut sums = vec![0.0; types_num];
+        let mut counts = vec![0usize; types_num];
+        for (_, particle) in self.elements.iter() {
+            let p_type = particle.physics_type as usize;
+            if p_type >= types_num {
+                continue;
+            }
+            if let Some((vel, _)) = physics.get_object_velocity(particle.get_rigid_handle()) {
+                sums[p_type] += vel.length();
+                counts[p_type] += 1;
+            }
+        }
+        return sums.iter().zip(counts.iter()).map(|(sum, n)| if *n > 0 { sum / *n as f32 } else { 0.0 }).collect();
+    }
+
+    /// Draws a new random type for every element, used when the number of types changes.
+    pub fn randomize_types(&mut self, physics: &mut Physics) {
+        let types_num = physics.types.types_num();
+        for (_, particle) in self.elements.iter_mut() {
+            let p_type = rand::gen_range(0, types_num) as u128;
+            let color = physics.get_physics_type(p_type).get_color();
+            particle.set_physics_type(p_type, color, physics);
+        }
+        physics.update_neighbors();
+    }
+
+    pub fn insert(&mut self, element: Element) -> (u64, RigidBodyHandle) {
+        let key = element.key;
+        let rbh = element.rigid_handle;
+        self.elements.insert(key, element);
+        return (key, rbh);
+    }
+
     /* pub fn add_motor(&mut self, position: Vec2, physics: &mut Physics) {
         let (k1, rbh1) = self.add_element(Some(position.clone()), YELLOW, Some(6.0), false, physics);
         let (k2, rbh2) = self.add_element(Some(position+Vec2::new(64.0, 0.0)), RED, Some(6.0), false, physics);
@@ -302,6 +518,32 @@ impl ElementCollector {
         return self.elements.get(&id);
     }
 
+    pub fn keys_in_range(&self, pos: Vec2, radius: f32) -> Vec<u64> {
+        return self.elements.iter()
+            .filter(|(_, element)| element.pos.distance(pos) <= radius)
+            .map(|(key, _)| *key)
+            .collect();
+    }
+
+    /// Adds an element at `position` with the given type instead of a random one.
+    pub fn spawn(&mut self, position: Vec2, p_type: u128, physics: &mut Physics) -> (u64, RigidBodyHandle) {
+        let (key, rbh) = self.add_element(Some(position), GREEN, None, false, physics);
+        let color = physics.get_physics_type(p_type).get_color();
+        if let Some(element) = self.elements.get_mut(&key) {
+            element.set_physics_type(p_type, color, physics);
+        }
+        return (key, rbh);
+    }
+
+    /// Key of the element closest to `pos` within `radius`.
+    pub fn find_nearest(&self, pos: Vec2, radius: f32) -> Option<u64> {
+        return self.elements.iter()
+            .map(|(key, element)| (*key, element.pos.distance(pos)))
+            .filter(|(_, dist)| *dist <= radius)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(key, _)| key);
+    }
+
     pub fn remove(&mut self, id: u64) {
         self.elements.remove(&id);
     }
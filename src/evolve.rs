@@ -0,0 +1,228 @@
+use std::io;
+use std::path::PathBuf;
+use macroquad::rand;
+use crate::cluster::*;
+use crate::globals::*;
+use crate::headless::*;
+use crate::physics_types::*;
+use crate::util::*;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Fitness {
+    /// Number of clusters at the end of the run.
+    Clusters,
+    /// Coefficient of variation of the total kinetic energy, rewards worlds that don't settle down.
+    EnergyVariance,
+    /// Mean net distance traveled by a particle, rewards structures that move as a whole.
+    Motility,
+}
+
+impl Fitness {
+
+    pub fn all() -> [Fitness; 3] {
+        return [Fitness::Clusters, Fitness::EnergyVariance, Fitness::Motility];
+    }
+
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Fitness::Clusters => "clusters",
+            Fitness::EnergyVariance => "energy_variance",
+            Fitness::Motility => "motility",
+        };
+    }
+}
+
+//?         [[[EVOLVE_CONFIG]]]
+pub struct EvolveConfig {
+    pub generations: u32,
+    pub population: usize,
+    pub elite: usize,
+    pub steps: u64,
+    pub mutation: f32,
+    pub fitness: Fitness,
+    pub seed: Option<u64>,
+    pub out: PathBuf,
+}
+
+impl Default for EvolveConfig {
+    fn default() -> Self {
+        Self {
+            generations: 10,
+            population: 16,
+            elite: 4,
+            steps: 1200,
+            mutation: 0.1,
+            fitness: Fitness::Clusters,
+            seed: None,
+            out: PathBuf::from(RULES_DIR),
+        }
+    }
+}
+
+impl EvolveConfig {
+
+    pub const USAGE: &'static str = "usage: madlab --evolve [--generations N] [--population N] [--elite N] [--steps N] [--mutation RATE] [--fitness clusters|energy_variance|motility] [--seed SEED] [--out DIR] [--particles N] [--types N] [--search grid|rapier] [--threads N] [--boundary soft|hard|box|torus|open]";
+
+    /// Returns `Ok(None)` when `--evolve` is not among the arguments.
+    pub fn from_args(args: &[String]) -> Result<Option<Self>, String> {
+        if !args.iter().any(|arg| arg == "--evolve") {
+            return Ok(None);
+        }
+        let mut config = Self::default();
+        let mut settings = get_settings();
+        let mut i = 0;
+        while i < args.len() {
+            let arg = args[i].as_str();
+            if arg == "--evolve" {
+                i += 1;
+                continue;
+            }
+            let value = args.get(i+1).ok_or(format!("missing value for {}", arg))?;
+            match arg {
+                "--generations" => config.generations = parse_number(arg, value)? as u32,
+                "--population" => config.population = (parse_number(arg, value)? as usize).max(2),
+                "--elite" => config.elite = parse_number(arg, value)? as usize,
+                "--steps" => config.steps = parse_number(arg, value)?.max(1),
+                "--seed" => config.seed = Some(parse_number(arg, value)?),
+                "--mutation" => config.mutation = value.parse::<f32>().map_err(|_| format!("invalid mutation rate: {}", value))?,
+                "--out" => config.out = PathBuf::from(value),
+                "--fitness" => config.fitness = Fitness::all().into_iter()
+                    .find(|f| f.name() == value)
+                    .ok_or(format!("unknown fitness: {}", value))?,
+                _ => {
+                    if !parse_settings_arg(arg, value, &mut settings)? {
+                        return Err(format!("unknown argument: {}", arg));
+                    }
+                },
+            }
+            i += 2;
+        }
+        config.elite = config.elite.clamp(1, config.population);
+        set_settings(settings);
+        Ok(Some(config))
+    }
+}
+
+//?         [[[CANDIDATE]]]
+#[derive(Clone)]
+pub struct Candidate {
+    pub rules: RuleSet,
+    pub fitness: f32,
+}
+
+/// Runs one rule set headlessly from the world built by `seed` and scores it.
+pub fn evaluate(rules: &RuleSet, seed: u64, steps: u64, fitness: Fitness) -> io::Result<f32> {
+    let mut sim = Headless::new(seed);
+    sim.world.set_types(PhysicsTypes::from_rule_set(rules)?);
+    sim.elements.apply_types(&mut sim.world);
+    // the first quarter of the run is a warm-up, the world is still random
+    let warm_up = steps / 4;
+    let mut energy: Vec<f32> = vec![];
+    let mut travel: Vec<(f32, f32)> = vec![(0.0, 0.0); sim.elements.count()];
+    for step in 0..steps {
+        sim.step();
+        if step < warm_up {
+            continue;
+        }
+        match fitness {
+            Fitness::EnergyVariance => energy.push(sim.world.get_total_kinetic_eng()),
+            Fitness::Motility => {
+                let dt = sim.world.get_dt();
+                for ((_, element), (dx, dy)) in sim.elements.get_iter().zip(travel.iter_mut()) {
+                    if let Some((vel, _)) = sim.world.get_object_velocity(element.get_rigid_handle()) {
+                        *dx += vel.x * dt;
+                        *dy += vel.y * dt;
+                    }
+                }
+            },
+            Fitness::Clusters => {},
+        }
+    }
+    let score = match fitness {
+        Fitness::Clusters => {
+            let mut clusters = ClusterAnalysis::with_min_size(5);
+            clusters.analyze(&sim.elements, sim.world.types.types_num(), sim.sim_time);
+            clusters.report.clusters.len() as f32
+        },
+        Fitness::EnergyVariance => {
+            let n = energy.len().max(1) as f32;
+            let mean = energy.iter().sum::<f32>() / n;
+            let variance = energy.iter().map(|e| (e - mean).powi(2)).sum::<f32>() / n;
+            if mean > 0.0 { variance.sqrt() / mean } else { 0.0 }
+        },
+        Fitness::Motility => {
+            let n = travel.len().max(1) as f32;
+            travel.iter().map(|(dx, dy)| (dx * dx + dy * dy).sqrt()).sum::<f32>() / n
+        },
+    };
+    return Ok(score);
+}
+
+fn tournament(population: &[Candidate]) -> &Candidate {
+    let mut best = &population[rand::gen_range(0, population.len())];
+    for _ in 0..2 {
+        let other = &population[rand::gen_range(0, population.len())];
+        if other.fitness > best.fitness {
+            best = other;
+        }
+    }
+    return best;
+}
+
+/// Uniform crossover of whole matrix rows (a type's reactions to all the others), then mutation.
+fn offspring(a: &RuleSet, b: &RuleSet, mutation: f32) -> RuleSet {
+    let mut child = a.clone();
+    for n in 0..child.types_num {
+        if rand::gen_range(0.0, 1.0) < 0.5 {
+            child.actions[n] = b.actions[n].clone();
+            child.fields[n] = b.fields[n];
+        }
+        for action in child.actions[n].iter_mut() {
+            if rand::gen_range(0.0, 1.0) < mutation {
+                *action = (*action + rand::gen_range(-0.5, 0.5)).clamp(-1.0, 1.0);
+            }
+        }
+        if rand::gen_range(0.0, 1.0) < mutation {
+            child.fields[n] = (child.fields[n] + rand::gen_range(-0.25, 0.25)).clamp(0.5, 1.5);
+        }
+    }
+    return child;
+}
+
+/// Genetic search over interaction matrices. Every candidate starts from the same world (same seed),
+/// so scores only differ by the rules. The best rule set of every generation is saved into `out`.
+pub fn run_evolve(config: &EvolveConfig) -> io::Result<Vec<Candidate>> {
+    let seed = config.seed.unwrap_or_else(generate_seed);
+    let world_seed = splitmix64(seed);
+    println!("SEED: {} | FITNESS: {} | POPULATION: {} | GENERATIONS: {}", seed, config.fitness.name(), config.population, config.generations);
+    // elites keep their score, evaluation is deterministic for the same world seed
+    let mut pool: Vec<(RuleSet, Option<f32>)> = (0..config.population).map(|i| {
+        rand::srand(splitmix64(seed ^ i as u64));
+        (PhysicsTypes::random().to_rule_set(), None)
+    }).collect();
+    let mut population: Vec<Candidate> = vec![];
+    for generation in 0..config.generations {
+        population.clear();
+        for (rules, score) in pool.drain(..) {
+            let fitness = match score {
+                Some(fitness) => fitness,
+                None => evaluate(&rules, world_seed, config.steps, config.fitness)?,
+            };
+            population.push(Candidate { rules, fitness });
+        }
+        population.sort_by(|a, b| b.fitness.total_cmp(&a.fitness));
+        let mean = population.iter().map(|c| c.fitness).sum::<f32>() / population.len() as f32;
+        let path = config.out.join(format!("evolved_{}_{}_g{:03}.{}", config.fitness.name(), seed, generation, RULES_EXT));
+        PhysicsTypes::from_rule_set(&population[0].rules)?.save(&path)?;
+        println!("generation {:3} | best {:10.4} | mean {:10.4} | saved {}", generation, population[0].fitness, mean, path.display());
+        // breeding draws from its own seeded stream, evaluation re-seeds the global generator
+        rand::srand(splitmix64(seed.wrapping_add(generation as u64 + 1)));
+        pool.extend(population.iter().take(config.elite).map(|c| (c.rules.clone(), Some(c.fitness))));
+        while pool.len() < config.population {
+            let a = tournament(&population);
+            let b = tournament(&population);
+            pool.push((offspring(&a.rules, &b.rules, config.mutation), None));
+        }
+    }
+    return Ok(population);
+}
@@ -0,0 +1,163 @@
+use serde::{Deserialize, Serialize};
+
+//?         [[[FORCE_LAW]]]
+/// Radial force kernel used by `Physics::field_react`. `scalar` takes the distance relative to
+/// the field range (0..1) and the interaction `action` (-1..1) and returns the force multiplier:
+/// positive pulls the particle toward its neighbor, negative pushes it away.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+pub enum ForceLaw {
+    /// The original madlab law: `a/r` outside the repel zone, scaled inverse inside, constant core.
+    #[default]
+    Classic,
+    /// Linear "particle life" triangle: linear repulsion below `beta`, triangular bump above.
+    ParticleLife { beta: f32 },
+    /// Lennard-Jones 12-6 force `-dU/dr`, zero at `2^(1/6) * sigma`, softened below `0.8 * sigma`.
+    LennardJones { sigma: f32, epsilon: f32 },
+    /// Soft linear core with a gaussian attraction/repulsion shell.
+    Gaussian { core: f32, width: f32 },
+    /// Morse potential with equilibrium distance `r0`.
+    Morse { r0: f32, alpha: f32, depth: f32 },
+}
+
+impl ForceLaw {
+
+    pub fn all() -> [ForceLaw; 5] {
+        return [
+            ForceLaw::Classic,
+            ForceLaw::ParticleLife { beta: 0.3 },
+            ForceLaw::LennardJones { sigma: 0.3, epsilon: 0.05 },
+            ForceLaw::Gaussian { core: 0.3, width: 0.15 },
+            ForceLaw::Morse { r0: 0.4, alpha: 3.0, depth: 1.0 },
+        ];
+    }
+
+    pub fn name(&self) -> &'static str {
+        return match self {
+            ForceLaw::Classic => "Classic",
+            ForceLaw::ParticleLife { .. } => "Particle Life",
+            ForceLaw::LennardJones { .. } => "Lennard-Jones",
+            ForceLaw::Gaussian { .. } => "Gaussian",
+            ForceLaw::Morse { .. } => "Morse",
+        };
+    }
+
+    pub fn same_kind(&self, other: &ForceLaw) -> bool {
+        return std::mem::discriminant(self) == std::mem::discriminant(other);
+    }
+
+    /// `repel` and `repel_on` are only used by the classic law.
+    pub fn scalar(&self, rel_dist: f32, action: f32, repel: f32, repel_on: bool) -> f32 {
+        let r = rel_dist;
+        let a = action;
+        return match *self {
+            ForceLaw::Classic => {
+                if r > repel && r != 0.0 {
+                    a / r
+                } else if r > repel/2.0 {
+                    if repel_on { -a / r / repel } else { 0.0 }
+                } else {
+                    -1.0
+                }
+            },
+            ForceLaw::ParticleLife { beta } => {
+                if r < beta {
+                    r / beta - 1.0
+                } else if r < 1.0 {
+                    a * (1.0 - (2.0 * r - 1.0 - beta).abs() / (1.0 - beta))
+                } else {
+                    0.0
+                }
+            },
+            ForceLaw::LennardJones { sigma, epsilon } => {
+                let r = r.max(0.8 * sigma);
+                let x = (sigma / r).powi(6);
+                24.0 * epsilon / r * (a * x - 2.0 * x * x)
+            },
+            ForceLaw::Gaussian { core, width } => {
+                let repulsion = if r < core { 1.0 - r / core } else { 0.0 };
+                let mid = (1.0 + core) / 2.0;
+                let shell = a * (-(r - mid).powi(2) / (2.0 * width * width)).exp();
+                shell - repulsion
+            },
+            ForceLaw::Morse { r0, alpha, depth } => {
+                let e1 = (-alpha * (r - r0)).exp();
+                depth * (a * e1 - e1 * e1)
+            },
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Field law of `Physics::field_react` before force laws were added, with `force` = 1.
+    fn old_law(rel_dist: f32, a: f32, repel: f32, repel_on: bool) -> f32 {
+        let mut scalar = 0.0;
+        if rel_dist > repel && rel_dist != 0.0 {
+            scalar = a / rel_dist;
+        } else if rel_dist > repel/2.0 {
+            if repel_on {
+                scalar = -a / rel_dist/repel;
+            }
+        } else {
+            scalar = -1.0;
+        }
+        return scalar;
+    }
+
+    /// Distance in `0..1` where `scalar` goes from negative (push) to positive (pull).
+    fn zero_crossing(law: &ForceLaw, action: f32) -> f32 {
+        let steps = 10000;
+        let mut prev = law.scalar(0.0, action, 0.0, false);
+        for i in 1..=steps {
+            let r = i as f32 / steps as f32;
+            let value = law.scalar(r, action, 0.0, false);
+            if prev < 0.0 && value >= 0.0 {
+                return r;
+            }
+            prev = value;
+        }
+        panic!("{} never crosses zero", law.name());
+    }
+
+    #[test]
+    fn classic_matches_the_old_law() {
+        for repel in [0.1, 0.3, 0.5] {
+            for repel_on in [false, true] {
+                for a in [-1.0, -0.4, 0.0, 0.7, 1.0] {
+                    for i in 0..=100 {
+                        let r = i as f32 / 100.0;
+                        assert_eq!(ForceLaw::Classic.scalar(r, a, repel, repel_on), old_law(r, a, repel, repel_on),
+                            "r {} a {} repel {} repel_on {}", r, a, repel, repel_on);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn lennard_jones_crosses_zero_at_its_minimum() {
+        let sigma = 0.3;
+        let law = ForceLaw::LennardJones { sigma, epsilon: 1.0 };
+        let r = zero_crossing(&law, 1.0);
+        assert!((r - 2f32.powf(1.0 / 6.0) * sigma).abs() < 1e-3, "crossing at {}", r);
+        assert!(law.scalar(sigma, 1.0, 0.0, false) < 0.0);
+    }
+
+    #[test]
+    fn morse_crosses_zero_at_r0() {
+        let law = ForceLaw::Morse { r0: 0.4, alpha: 3.0, depth: 1.0 };
+        let r = zero_crossing(&law, 1.0);
+        assert!((r - 0.4).abs() < 1e-3, "crossing at {}", r);
+    }
+
+    #[test]
+    fn softened_cores_stay_finite() {
+        for law in ForceLaw::all() {
+            for a in [-1.0, 0.0, 1.0] {
+                assert!(law.scalar(0.0, a, 0.3, true).is_finite(), "{} at r = 0", law.name());
+            }
+        }
+    }
+}
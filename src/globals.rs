@@ -1,17 +1,31 @@
 #![allow(unused)]
 use macroquad::experimental::collections::storage;
+use serde::{Deserialize, Serialize};
+use crate::bonding::BondSettings;
+use crate::boundary::*;
+use crate::camera::Follow;
+use crate::force_law::*;
+use crate::solver::SolverSettings;
+use crate::spatial_hash::NeighborSearch;
+use crate::spawn_layout::*;
 
 
 pub const SCREEN_WIDTH: f32 = 950.0;
 pub const SCREEN_HEIGHT: f32 = 950.0;
 
 pub const ZOOM_RATE: f32 = 1.0 / 200.0;
-pub const SCREEN_RATIO: f32 = 1.0;
-//pub const SCREEN_RATIO: f32 = SCREEN_HEIGHT / SCREEN_WIDTH;
 
-pub const TYPES_NUM: usize = 19;
+pub const RULES_DIR: &str = "rules";
+pub const RULES_EXT: &str = "json";
+pub const SNAPSHOTS_DIR: &str = "snapshots";
+pub const SNAPSHOTS_EXT: &str = "json";
+pub const CAPTURES_DIR: &str = "captures";
+pub const SERIES_DIR: &str = "series";
+pub const SERIES_EXT: &str = "csv";
+pub const PRESETS_DIR: &str = "presets";
+pub const PRESETS_EXT: &str = "json";
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub enum DisplayMode {
     ELEMENTS,
     STROKE,
@@ -42,7 +56,7 @@ pub fn signals() -> Signals {
     return *storage::get_mut::<Signals>();
 } */
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub struct Settings {
     pub world_radius: f32,
     pub field: f32,
@@ -50,6 +64,9 @@ pub struct Settings {
     pub repel: f32,
     pub particles_num: usize,
     pub particle_types: usize,
+    /// Upper end of the types slider and most types a loaded rule set can have.
+    #[serde(default = "default_max_types")]
+    pub max_types: usize,
     pub particle_size: f32,
     pub particle_dense: f32,
     pub damping: f32,
@@ -57,6 +74,37 @@ pub struct Settings {
     pub field_range: bool,
     pub repel_on: bool,
     pub collisions: bool,
+    #[serde(default)]
+    pub force_law: ForceLaw,
+    #[serde(default)]
+    pub neighbor_search: NeighborSearch,
+    #[serde(default = "default_threads")]
+    pub threads: usize,
+    #[serde(default)]
+    pub boundary: Boundary,
+    #[serde(default)]
+    pub layout: SpawnLayout,
+    #[serde(default)]
+    pub follow: Follow,
+    /// Simulated seconds per real second.
+    #[serde(default = "default_time_scale")]
+    pub time_scale: f32,
+    #[serde(default)]
+    pub solver: SolverSettings,
+    #[serde(default)]
+    pub bonds: BondSettings,
+}
+
+pub fn default_time_scale() -> f32 {
+    return 1.0;
+}
+
+pub fn default_max_types() -> usize {
+    return 100;
+}
+
+pub fn default_threads() -> usize {
+    return std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
 }
 
 impl Default for Settings {
@@ -68,13 +116,23 @@ impl Default for Settings {
             repel: 0.55,
             particles_num: 2000,
             particle_types: 19,
+            max_types: default_max_types(),
             particle_size: 1.0,
             particle_dense: 1.0,
             damping: 1.0,
             display: DisplayMode::ELEMENTS,
             field_range: false,
             repel_on: true,
-            collisions: false,
+            collisions: true,
+            force_law: ForceLaw::Classic,
+            neighbor_search: NeighborSearch::Grid,
+            threads: default_threads(),
+            boundary: Boundary::SoftCircle { stiffness: 0.2 },
+            layout: SpawnLayout::Disk,
+            follow: Follow::Off,
+            time_scale: 1.0,
+            solver: SolverSettings::default(),
+            bonds: BondSettings::default(),
        }
     }
 }
@@ -86,6 +144,13 @@ pub struct Signals {
     pub restart: bool,
     pub shuffle_interactions: bool,
     pub particles_new_settings: bool,
+    pub save_rules: bool,
+    pub save_snapshot: bool,
+    pub types_num_changed: bool,
+    pub export_series: bool,
+    pub toggle_fullscreen: bool,
+    pub toggle_pause: bool,
+    pub single_step: bool,
 }
 
 impl Default for Signals {
@@ -96,6 +161,13 @@ impl Default for Signals {
             restart: false,
             shuffle_interactions: false,
             particles_new_settings: false,
+            save_rules: false,
+            save_snapshot: false,
+            types_num_changed: false,
+            export_series: false,
+            toggle_fullscreen: false,
+            toggle_pause: false,
+            single_step: false,
         }
     }
 }
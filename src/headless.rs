@@ -0,0 +1,193 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use crate::boundary::Boundary;
+use crate::element::*;
+use crate::globals::*;
+use crate::physics::*;
+use crate::spatial_hash::NeighborSearch;
+use crate::util::*;
+use macroquad::rand;
+
+//?         [[[HEADLESS_CONFIG]]]
+pub struct HeadlessConfig {
+    pub steps: u64,
+    pub duration: Option<f32>,
+    pub seed: Option<u64>,
+    pub rules: Option<PathBuf>,
+    pub out: Option<PathBuf>,
+    pub report_every: u64,
+}
+
+impl Default for HeadlessConfig {
+    fn default() -> Self {
+        Self {
+            steps: 3600,
+            duration: None,
+            seed: None,
+            rules: None,
+            out: None,
+            report_every: 60,
+        }
+    }
+}
+
+impl HeadlessConfig {
+
+    pub const USAGE: &'static str = "usage: madlab --headless [--steps N | --duration SECONDS] [--seed SEED] [--rules FILE] [--out FILE.csv] [--every N] [--particles N] [--types N] [--search grid|rapier] [--threads N] [--boundary soft|hard|box|torus|open]";
+
+    /// Parses command line arguments (without the program name). Returns `Ok(None)` when
+    /// `--headless` is not among them, so the regular windowed app should start.
+    pub fn from_args(args: &[String]) -> Result<Option<Self>, String> {
+        if !args.iter().any(|arg| arg == "--headless") {
+            return Ok(None);
+        }
+        let mut config = Self::default();
+        let mut settings = get_settings();
+        let mut i = 0;
+        while i < args.len() {
+            let arg = args[i].as_str();
+            if arg == "--headless" {
+                i += 1;
+                continue;
+            }
+            let value = args.get(i+1).ok_or(format!("missing value for {}", arg))?;
+            match arg {
+                "--steps" => config.steps = parse_number(arg, value)?,
+                "--seed" => config.seed = Some(parse_number(arg, value)?),
+                "--every" => config.report_every = parse_number(arg, value)?.max(1),
+                "--duration" => config.duration = Some(value.parse::<f32>().map_err(|_| format!("invalid duration: {}", value))?),
+                "--rules" => config.rules = Some(PathBuf::from(value)),
+                "--out" => config.out = Some(PathBuf::from(value)),
+                _ => {
+                    if !parse_settings_arg(arg, value, &mut settings)? {
+                        return Err(format!("unknown argument: {}", arg));
+                    }
+                },
+            }
+            i += 2;
+        }
+        set_settings(settings);
+        Ok(Some(config))
+    }
+}
+
+pub fn parse_number(arg: &str, value: &str) -> Result<u64, String> {
+    return value.parse::<u64>().map_err(|_| format!("invalid number for {}: {}", arg, value));
+}
+
+/// World settings shared by all command line modes. Returns `Ok(false)` for arguments it doesn't know.
+pub fn parse_settings_arg(arg: &str, value: &str, settings: &mut Settings) -> Result<bool, String> {
+    match arg {
+        "--particles" => settings.particles_num = parse_number(arg, value)? as usize,
+        "--types" => {
+            let types = parse_number(arg, value)? as usize;
+            if types == 0 {
+                return Err("--types must be at least 1".to_string());
+            }
+            settings.particle_types = types;
+            settings.max_types = settings.max_types.max(types);
+        },
+        "--threads" => settings.threads = (parse_number(arg, value)? as usize).max(1),
+        "--boundary" => settings.boundary = Boundary::all().into_iter()
+            .find(|b| b.cli_name() == value)
+            .ok_or(format!("unknown boundary: {} (expected soft|hard|box|torus|open)", value))?,
+        "--search" => settings.neighbor_search = match value {
+            "grid" => NeighborSearch::Grid,
+            "rapier" => NeighborSearch::Rapier,
+            _ => return Err(format!("unknown neighbor search: {}", value)),
+        },
+        _ => return Ok(false),
+    }
+    return Ok(true);
+}
+
+//?         [[[METRICS]]]
+#[derive(Clone, Copy)]
+pub struct Metrics {
+    pub step: u64,
+    pub sim_time: f64,
+    pub objects: usize,
+    pub total_k_eng: f32,
+}
+
+impl Metrics {
+
+    pub const CSV_HEADER: &'static str = "step,sim_time,objects,total_k_eng";
+
+    pub fn to_csv(self) -> String {
+        return format!("{},{:.4},{},{:.4}", self.step, self.sim_time, self.objects, self.total_k_eng);
+    }
+}
+
+//?         [[[HEADLESS]]]
+/// Physics world and particles without window, font or camera.
+pub struct Headless {
+    pub world: Physics,
+    pub elements: ElementCollector,
+    pub seed: u64,
+    pub steps: u64,
+    pub sim_time: f64,
+}
+
+impl Headless {
+
+    pub fn new(seed: u64) -> Self {
+        rand::srand(seed);
+        let mut world = Physics::new();
+        let mut elements = ElementCollector::new();
+        elements.add_many_elements(get_settings().particles_num, &mut world);
+        Self { world, elements, seed, steps: 0, sim_time: 0.0 }
+    }
+
+    pub fn step(&mut self) {
+        self.elements.update_all(&mut self.world);
+        self.world.step_physics();
+        self.steps += 1;
+        self.sim_time += self.world.get_dt() as f64;
+    }
+
+    pub fn metrics(&self) -> Metrics {
+        return Metrics {
+            step: self.steps,
+            sim_time: self.sim_time,
+            objects: self.elements.count(),
+            total_k_eng: self.world.get_total_kinetic_eng(),
+        };
+    }
+}
+
+pub fn run_headless(config: &HeadlessConfig) -> io::Result<Metrics> {
+    let seed = config.seed.unwrap_or_else(generate_seed);
+    let mut sim = Headless::new(seed);
+    if let Some(path) = &config.rules {
+        sim.world.load_types(path)?;
+        sim.elements.apply_types(&mut sim.world);
+    }
+    let steps = match config.duration {
+        Some(seconds) => (seconds / sim.world.get_dt()).ceil() as u64,
+        None => config.steps,
+    };
+    println!("SEED: {} | PARTICLES: {} | STEPS: {}", seed, sim.elements.count(), steps);
+    let mut csv: Vec<String> = vec![Metrics::CSV_HEADER.to_string(), sim.metrics().to_csv()];
+    for _ in 0..steps {
+        sim.step();
+        if sim.steps.is_multiple_of(config.report_every.max(1)) {
+            csv.push(sim.metrics().to_csv());
+        }
+    }
+    let metrics = sim.metrics();
+    if !metrics.step.is_multiple_of(config.report_every.max(1)) {
+        csv.push(metrics.to_csv());
+    }
+    if let Some(path) = &config.out {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = fs::File::create(path)?;
+        writeln!(file, "{}", csv.join("\n"))?;
+    }
+    println!("{}", Metrics::CSV_HEADER);
+    println!("{}", metrics.to_csv());
+    return Ok(metrics);
+}
@@ -13,9 +13,26 @@ mod ui;
 mod globals;
 mod dbg;
 mod physics_types;
+mod force_law;
+mod spatial_hash;
+mod boundary;
+mod brush;
+mod snapshot;
+mod headless;
+mod recorder;
+mod monitor;
+mod cluster;
+mod evolve;
+mod spawn_layout;
+mod preset;
+mod solver;
+mod bonding;
+mod reaction;
 
 use std::time::{SystemTime, UNIX_EPOCH};
+use crate::evolve::*;
 use crate::globals::*;
+use crate::headless::*;
 use crate::sim::*;
 use crate::util::*;
 use macroquad::prelude::*;
@@ -26,12 +43,12 @@ struct App {
 
 impl App {
     
-    pub fn new(font: Font) -> App {
+    pub fn new(font: Font, seed: u64) -> App {
         let config = SimConfig::default();
         let signals = Signals::default();
         set_signals(signals);
         Self {
-            sim: Simulation::new(config, font.clone()),
+            sim: Simulation::new(config, font.clone(), seed),
         }
     }
 
@@ -39,12 +56,8 @@ impl App {
         loop {
             self.sim.input();
             //self.sim.process_ui();
-            if self.sim.is_running() {
-                self.sim.update();
-                self.sim.draw();
-            } else {
-                self.sim.signals_check();
-            }
+            self.sim.update();
+            self.sim.draw();
             //self.sim.draw_ui();
             next_frame().await;
         }        
@@ -57,22 +70,54 @@ fn app_configuration() -> Conf {
         window_width: SCREEN_WIDTH as i32,
         window_height: SCREEN_HEIGHT as i32,
         sample_count: 16,
-        window_resizable: false,
+        window_resizable: true,
         fullscreen: false,
         high_dpi: true,
         ..Default::default()
     }
 }
 
-#[macroquad::main(app_configuration)]
-async fn main() {
+fn main() {
+    let settings = Settings::default();
+    set_settings(settings);
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match EvolveConfig::from_args(&args) {
+        Ok(Some(config)) => {
+            if let Err(e) = run_evolve(&config) {
+                eprintln!("evolutionary search failed: {}", e);
+                std::process::exit(1);
+            }
+            return;
+        },
+        Ok(None) => {},
+        Err(e) => {
+            eprintln!("{}\n{}", e, EvolveConfig::USAGE);
+            std::process::exit(2);
+        },
+    }
+    match HeadlessConfig::from_args(&args) {
+        Ok(Some(config)) => {
+            if let Err(e) = run_headless(&config) {
+                eprintln!("headless run failed: {}", e);
+                std::process::exit(1);
+            }
+        },
+        Ok(None) => {
+            macroquad::Window::from_config(app_configuration(), windowed_main());
+        },
+        Err(e) => {
+            eprintln!("{}\n{}", e, HeadlessConfig::USAGE);
+            std::process::exit(2);
+        },
+    }
+}
+
+async fn windowed_main() {
     let seed = generate_seed();
     println!("SEED: {}", seed);
     rand::srand(seed);  
-    let settings = Settings::default();
-    set_settings(settings);
     let font = load_ttf_font("jetbrain.ttf").await.expect("can't load font resource!");
-    let mut app = App::new(font);
+    let mut app = App::new(font, seed);
     app.run().await;
 
 }
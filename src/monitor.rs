@@ -0,0 +1,155 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::path::Path;
+use crate::bonding::BondStats;
+
+//?         [[[SAMPLE]]]
+/// Values recorded for a single frame.
+#[derive(Clone)]
+pub struct Sample {
+    pub sim_time: f64,
+    pub total_k_eng: f32,
+    pub fps: i32,
+    pub step_time: f32,
+    pub type_speeds: Vec<f32>,
+    pub bonds: BondStats,
+}
+
+//?         [[[MONITOR]]]
+/// Rolling buffer of the last `span` seconds of samples, plotted in the Monitor window.
+pub struct Monitor {
+    samples: VecDeque<Sample>,
+    pub span: f32,
+    pub paused: bool,
+}
+
+impl Default for Monitor {
+    fn default() -> Self {
+        Self {
+            samples: VecDeque::new(),
+            span: 30.0,
+            paused: false,
+        }
+    }
+}
+
+impl Monitor {
+
+    pub fn push(&mut self, sample: Sample) {
+        if self.paused {
+            return;
+        }
+        // sim time goes back to zero after a restart
+        if self.samples.back().is_some_and(|last| last.sim_time > sample.sim_time) {
+            self.samples.clear();
+        }
+        let oldest = sample.sim_time - self.span as f64;
+        self.samples.push_back(sample);
+        while self.samples.front().is_some_and(|first| first.sim_time < oldest) {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    pub fn samples(&self) -> &VecDeque<Sample> {
+        return &self.samples;
+    }
+
+    /// `[sim_time, value]` points of one series, ready to plot.
+    pub fn series(&self, value: impl Fn(&Sample) -> f64) -> Vec<[f64; 2]> {
+        return self.samples.iter().map(|s| [s.sim_time, value(s)]).collect();
+    }
+
+    pub fn types_num(&self) -> usize {
+        return self.samples.iter().map(|s| s.type_speeds.len()).max().unwrap_or(0);
+    }
+
+    pub fn to_csv(&self) -> String {
+        let types_num = self.types_num();
+        let mut csv = String::from("sim_time,total_k_eng,fps,step_time_ms,bonds,bonds_formed,bonds_broken");
+        for t in 0..types_num {
+            csv.push_str(&format!(",speed_{}", t));
+        }
+        csv.push('\n');
+        for s in self.samples.iter() {
+            csv.push_str(&format!("{:.4},{:.4},{},{:.4},{},{},{}", s.sim_time, s.total_k_eng, s.fps, s.step_time, s.bonds.count, s.bonds.formed, s.bonds.broken));
+            for t in 0..types_num {
+                match s.type_speeds.get(t) {
+                    Some(v) => csv.push_str(&format!(",{:.4}", v)),
+                    None => csv.push(','),
+                }
+            }
+            csv.push('\n');
+        }
+        return csv;
+    }
+
+    pub fn export(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.to_csv())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(sim_time: f64, type_speeds: Vec<f32>) -> Sample {
+        return Sample {
+            sim_time,
+            total_k_eng: 12.5,
+            fps: 60,
+            step_time: 1.25,
+            type_speeds,
+            bonds: BondStats { count: 3, formed: 5, broken: 2 },
+        };
+    }
+
+    #[test]
+    fn csv_has_a_speed_column_for_every_type_seen() {
+        let mut monitor = Monitor::default();
+        monitor.push(sample(0.5, vec![1.0]));
+        monitor.push(sample(1.0, vec![2.0, 3.0]));
+        let csv = monitor.to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines, vec![
+            "sim_time,total_k_eng,fps,step_time_ms,bonds,bonds_formed,bonds_broken,speed_0,speed_1",
+            "0.5000,12.5000,60,1.2500,3,5,2,1.0000,",
+            "1.0000,12.5000,60,1.2500,3,5,2,2.0000,3.0000",
+        ]);
+    }
+
+    #[test]
+    fn empty_monitor_exports_only_the_header() {
+        assert_eq!(Monitor::default().to_csv(), "sim_time,total_k_eng,fps,step_time_ms,bonds,bonds_formed,bonds_broken\n");
+    }
+
+    #[test]
+    fn samples_older_than_the_span_are_dropped() {
+        let mut monitor = Monitor { span: 2.0, ..Default::default() };
+        for i in 0..10 {
+            monitor.push(sample(i as f64 * 0.5, vec![]));
+        }
+        let times: Vec<f64> = monitor.samples().iter().map(|s| s.sim_time).collect();
+        assert_eq!(times, vec![2.5, 3.0, 3.5, 4.0, 4.5]);
+        // a restart starts a new series
+        monitor.push(sample(0.0, vec![]));
+        assert_eq!(monitor.samples().len(), 1);
+    }
+
+    #[test]
+    fn export_writes_the_csv() {
+        let mut monitor = Monitor::default();
+        monitor.push(sample(0.25, vec![4.0]));
+        let path = std::env::temp_dir().join(format!("madlab_monitor_{}", std::process::id())).join("series.csv");
+        monitor.export(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), monitor.to_csv());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -3,6 +3,8 @@
 use crossbeam::channel::{Receiver, Sender};
 use crossbeam::*;
 use rapier2d::parry::shape::Ball;
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
 use crate::globals::*;
 use crate::timer::Timer;
 use crate::util::*;
@@ -10,12 +12,21 @@ use macroquad::prelude::*;
 use macroquad::rand::*;
 use nalgebra::Point2;
 use rapier2d::{na::Vector2, prelude::*};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::f32::consts::PI;
+use std::io;
 use std::num::NonZeroUsize;
+use std::path::Path;
 use std::ops::Bound;
+use crate::bonding::*;
 use crate::dbg::MacroRapierDebugger;
 use crate::physics_types::*;
+use crate::solver::SolverSettings;
+use crate::spatial_hash::*;
+
+/// Smallest number of particles a field force task gets, below that handing work
+/// to another thread costs more than it saves.
+const MIN_PARTICLES_PER_TASK: usize = 256;
 
 pub struct Physics {
     pub rigid_bodies: RigidBodySet,
@@ -37,6 +48,14 @@ pub struct Physics {
     //event_handler: ChannelEventCollector,
     grav_time: Timer,
     pub types: PhysicsTypes,
+    neighbors: SpatialHash,
+    /// Collision toggle and filters the colliders were last updated with.
+    applied_collisions: (bool, Vec<u32>),
+    /// Bonds in the order they were formed.
+    bonds: Vec<Bond>,
+    bond_stats: BondStats,
+    /// Field force workers, kept between steps and rebuilt when `settings.threads` changes.
+    workers: Option<ThreadPool>,
     //pub types2: PhysicsTypes2,
 }
 
@@ -44,13 +63,7 @@ impl Physics {
 
     pub fn new() -> Self {
         let solver_params = IntegrationParameters {
-            max_ccd_substeps: 1,
-            prediction_distance: 0.005,
             dt: 1.0/60.0,
-            min_island_size: 32,
-            allowed_linear_error: 0.004,
-            num_solver_iterations: NonZeroUsize::new(1).unwrap(),
-            num_additional_friction_iterations: 2,
             ..Default::default()
         };
         let dbg_cfg = DebugRenderStyle {
@@ -60,10 +73,11 @@ impl Physics {
             impulse_joint_separation_color: [0.0, 0.0, 1.0, 1.0],
             ..Default::default()
         };
+        // bonds are drawn by the elements
         let dbg_mode = 
             //DebugRenderMode::COLLIDER_SHAPES | 
-            DebugRenderMode::IMPULSE_JOINTS | 
-            DebugRenderMode::JOINTS;
+            //DebugRenderMode::IMPULSE_JOINTS | 
+            DebugRenderMode::MULTIBODY_JOINTS;
             //DebugRenderMode::SOLVER_CONTACTS;
         Self {
             rigid_bodies: RigidBodySet::new(),
@@ -85,14 +99,53 @@ impl Physics {
             //event_handler: event_handler,
             grav_time: Timer::new(0.66, true, true, false),
             types: PhysicsTypes::random(),
+            neighbors: SpatialHash::new(),
+            applied_collisions: (true, vec![]),
+            bonds: vec![],
+            bond_stats: BondStats::default(),
+            workers: None,
             //types2: PhysicsTypes2::random(50),
-        }
+        }.with_solver(&get_settings().solver)
+    }
+
+    fn with_solver(mut self, solver: &SolverSettings) -> Self {
+        self.apply_solver(solver);
+        return self;
+    }
+
+    /// Updates the solver parameters of the live world, bodies and contacts are kept.
+    pub fn apply_solver(&mut self, solver: &SolverSettings) {
+        let params = &mut self.integration_parameters;
+        params.num_solver_iterations = NonZeroUsize::new(solver.solver_iterations.max(1)).unwrap();
+        params.num_additional_friction_iterations = solver.friction_iterations;
+        params.min_island_size = solver.min_island_size.max(1);
+        params.prediction_distance = solver.prediction_distance.max(0.0);
+        params.allowed_linear_error = solver.allowed_linear_error.max(0.0);
+        params.max_ccd_substeps = solver.max_ccd_substeps.max(1);
+        self.gravity = Vector2::new(solver.gravity[0], solver.gravity[1]);
     }
 
     pub fn random_types(&mut self) {
         self.types = PhysicsTypes::random();
     }
 
+    pub fn set_types(&mut self, types: PhysicsTypes) {
+        self.types = types;
+    }
+
+    pub fn save_types(&self, path: &Path) -> io::Result<()> {
+        return self.types.save(path);
+    }
+
+    pub fn load_types(&mut self, path: &Path) -> io::Result<()> {
+        self.types = PhysicsTypes::load(path)?;
+        Ok(())
+    }
+
+    pub fn get_dt(&self) -> f32 {
+        return self.integration_parameters.dt;
+    }
+
     fn update_intersections(&mut self) {
         self.query_pipeline.update(&self.rigid_bodies, &self.colliders);
     }
@@ -119,17 +172,16 @@ impl Physics {
     }
 
     pub fn add_collider(&mut self, body_handle: RigidBodyHandle, rel_position: &Vec2, rotation: f32, shape: SharedShape, physics_props: PhysicsProperties) -> ColliderHandle {
-        let mut collision_types = ActiveCollisionTypes::DYNAMIC_DYNAMIC;
         let settings = get_settings();
-        if settings.collisions {
-            collision_types = ActiveCollisionTypes::DYNAMIC_DYNAMIC;
-        }
+        let collision_types = Self::collision_types(settings.collisions);
+        let p_type = self.rigid_bodies.get(body_handle).map(|body| body.user_data).unwrap_or(0);
+        let groups = self.collision_groups(p_type);
         let iso = make_isometry(rel_position.x, rel_position.y, rotation);
         let collider = match shape.shape_type() {
             ShapeType::Ball => {
                 //let radius = shape.0.as_ball().unwrap().radius;
                 ColliderBuilder::new(shape).position(iso).density(physics_props.density).friction(physics_props.friction).restitution(physics_props.restitution)
-                    .active_collision_types(collision_types).active_events(ActiveEvents::empty())
+                    .active_collision_types(collision_types).collision_groups(groups).active_events(ActiveEvents::empty())
                     .build()
             },
             ShapeType::ConvexPolygon => {
@@ -143,6 +195,52 @@ impl Physics {
         return self.colliders.insert_with_parent(collider, body_handle, &mut self.rigid_bodies);
     }
 
+    /// Dynamic bodies only touch each other with collisions enabled, walls and kinematic bodies always.
+    fn collision_types(collisions: bool) -> ActiveCollisionTypes {
+        return match collisions {
+            true => ActiveCollisionTypes::default() | ActiveCollisionTypes::DYNAMIC_DYNAMIC,
+            false => ActiveCollisionTypes::default(),
+        };
+    }
+
+    fn collision_groups(&self, p_type: u128) -> InteractionGroups {
+        let group = PhysicsTypes::collision_group(p_type as usize);
+        let filter = self.types.collision_filter(group);
+        return InteractionGroups::new(Group::from_bits_truncate(1 << group), Group::from_bits_truncate(filter));
+    }
+
+    /// Re-applies the collision settings to the colliders of one body, e.g. after its type changed.
+    pub fn refresh_collider(&mut self, body_handle: RigidBodyHandle) {
+        let collision_types = Self::collision_types(get_settings().collisions);
+        let Some(body) = self.rigid_bodies.get(body_handle) else {
+            return;
+        };
+        let groups = self.collision_groups(body.user_data);
+        for &collider_handle in body.colliders() {
+            if let Some(collider) = self.colliders.get_mut(collider_handle) {
+                collider.set_active_collision_types(collision_types);
+                collider.set_collision_groups(groups);
+            }
+        }
+    }
+
+    /// Updates all colliders when the collisions toggle or the collision matrix changed since the last step.
+    fn sync_collisions(&mut self) {
+        let current = (get_settings().collisions, self.types.collisions.clone());
+        if current == self.applied_collisions {
+            return;
+        }
+        let collision_types = Self::collision_types(current.0);
+        for (_, collider) in self.colliders.iter_mut() {
+            let p_type = collider.parent().and_then(|handle| self.rigid_bodies.get(handle)).map(|body| body.user_data).unwrap_or(0);
+            let group = PhysicsTypes::collision_group(p_type as usize);
+            let filter = self.types.collision_filter(group);
+            collider.set_active_collision_types(collision_types);
+            collider.set_collision_groups(InteractionGroups::new(Group::from_bits_truncate(1 << group), Group::from_bits_truncate(filter)));
+        }
+        self.applied_collisions = current;
+    }
+
     pub fn add_dynamic(&mut self, position: &Vec2, rotation: f32, shape: SharedShape, physics_props: PhysicsProperties, _random_vel: bool, p_type: u128) -> RigidBodyHandle {
         let rbh = self.add_dynamic_rigidbody(position, rotation, physics_props.linear_damping, physics_props.angular_damping, p_type);
         let _colh = self.add_collider(rbh, &Vec2::ZERO, 0.0, shape, physics_props);
@@ -160,6 +258,183 @@ impl Physics {
         return bound_handle;
     }
 
+    /// Breaks overloaded and overstretched bonds, then bonds the closest pairs which are within their
+    /// bond distance and both still have a free bond. Disabling bonding dissolves all bonds.
+    /// Spring forces are added on top of the field forces, so this runs right before the physics step.
+    fn update_bonds(&mut self) {
+        let settings = get_settings();
+        let bonding = settings.bonds;
+        if !bonding.enabled {
+            for bond in self.bonds.drain(..) {
+                if let Some(joint) = bond.joint {
+                    self.impulse_joint_set.remove(joint, true);
+                }
+            }
+            self.bond_stats.count = 0;
+            return;
+        }
+        // bonds of removed bodies (rope joints included) were removed together with them
+        let bodies = &self.rigid_bodies;
+        self.bonds.retain(|bond| bodies.contains(bond.body1) && bodies.contains(bond.body2));
+        let dt = self.get_dt();
+        let mut bonds = std::mem::take(&mut self.bonds);
+        let before = bonds.len();
+        bonds.retain(|bond| {
+            let (Some(pos1), Some(pos2)) = (self.get_object_position(bond.body1), self.get_object_position(bond.body2)) else {
+                return false;
+            };
+            let force = match bond.joint {
+                // a taut rope pulls through its distance limit
+                Some(joint) => self.impulse_joint_set.get(joint).map(|joint| {
+                    let limits: f32 = joint.data.limits.iter().map(|limit| limit.impulse.abs()).sum();
+                    (Vector2::new(joint.impulses.x, joint.impulses.y).norm() + limits) / dt
+                }).unwrap_or(0.0),
+                None => self.spring_force(bond, &bonding, pos1, pos2).length(),
+            };
+            let broken = (bonding.break_force > 0.0 && force > bonding.break_force) || pos1.distance(pos2) > bond.distance * bonding.break_stretch;
+            if broken {
+                if let Some(joint) = bond.joint {
+                    self.impulse_joint_set.remove(joint, true);
+                }
+            }
+            return !broken;
+        });
+        self.bond_stats.broken += before - bonds.len();
+        let mut counts: HashMap<RigidBodyHandle, usize> = HashMap::new();
+        let mut bonded: HashSet<(RigidBodyHandle, RigidBodyHandle)> = HashSet::new();
+        for bond in bonds.iter() {
+            *counts.entry(bond.body1).or_default() += 1;
+            *counts.entry(bond.body2).or_default() += 1;
+            bonded.insert((bond.body1, bond.body2));
+        }
+        if settings.neighbor_search == NeighborSearch::Rapier {
+            self.update_neighbors();
+        }
+        let free = |counts: &HashMap<RigidBodyHandle, usize>, handle: &RigidBodyHandle| counts.get(handle).copied().unwrap_or(0) < bonding.max_bonds;
+        let mut candidates: Vec<(f32, RigidBodyHandle, RigidBodyHandle, f32)> = vec![];
+        for particle in self.neighbors.entries() {
+            let range = self.types.max_bond_distance(particle.p_type as usize);
+            if range <= 0.0 || !free(&counts, &particle.handle) {
+                continue;
+            }
+            self.neighbors.for_each_in_range(particle.pos, range, |neighbor| {
+                // every pair once, the lower handle first
+                if neighbor.handle.into_raw_parts() <= particle.handle.into_raw_parts() {
+                    return;
+                }
+                let distance = self.types.bond_distance(particle.p_type as usize, neighbor.p_type as usize);
+                let dist = particle.pos.distance(neighbor.pos);
+                if distance > 0.0 && dist <= distance {
+                    candidates.push((dist, particle.handle, neighbor.handle, distance));
+                }
+            });
+        }
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+        for (_, body1, body2, distance) in candidates {
+            if !free(&counts, &body1) || !free(&counts, &body2) || bonded.contains(&(body1, body2)) {
+                continue;
+            }
+            // the grid sees across a periodic seam, bonds don't
+            match (self.get_object_position(body1), self.get_object_position(body2)) {
+                (Some(pos1), Some(pos2)) if pos1.distance(pos2) <= distance => {},
+                _ => continue,
+            }
+            bonds.push(self.make_bond(body1, body2, distance, bonding.kind));
+            bonded.insert((body1, body2));
+            *counts.entry(body1).or_default() += 1;
+            *counts.entry(body2).or_default() += 1;
+            self.bond_stats.formed += 1;
+        }
+        for bond in bonds.iter().filter(|bond| bond.joint.is_none()) {
+            if let (Some(pos1), Some(pos2)) = (self.get_object_position(bond.body1), self.get_object_position(bond.body2)) {
+                let force = self.spring_force(bond, &bonding, pos1, pos2);
+                self.apply_force(bond.body1, force);
+                self.apply_force(bond.body2, -force);
+            }
+        }
+        self.bond_stats.count = bonds.len();
+        self.bonds = bonds;
+    }
+
+    /// Rope bonds get their rapier joint here, spring bonds are driven by `spring_force`.
+    fn make_bond(&mut self, body1: RigidBodyHandle, body2: RigidBodyHandle, distance: f32, kind: BondKind) -> Bond {
+        let joint = match kind {
+            BondKind::Rope => Some(self.impulse_joint_set.insert(body1, body2, RopeJointBuilder::new(distance).build(), true)),
+            BondKind::Spring => None,
+        };
+        return Bond { body1, body2, distance, joint };
+    }
+
+    /// Puts back a bond saved in a snapshot.
+    pub fn restore_bond(&mut self, body1: RigidBodyHandle, body2: RigidBodyHandle, distance: f32, kind: BondKind) {
+        let bond = self.make_bond(body1, body2, distance, kind);
+        self.bonds.push(bond);
+        self.bond_stats.count = self.bonds.len();
+    }
+
+    /// Damped spring force pulling the first body of a spring bond toward its bond distance.
+    /// (rapier's own spring joint can't be solved in 2D yet)
+    fn spring_force(&self, bond: &Bond, bonding: &BondSettings, pos1: Vec2, pos2: Vec2) -> Vec2 {
+        let axis = (pos2 - pos1).normalize_or_zero();
+        let (vel1, _) = self.get_object_velocity(bond.body1).unwrap_or((Vec2::ZERO, 0.0));
+        let (vel2, _) = self.get_object_velocity(bond.body2).unwrap_or((Vec2::ZERO, 0.0));
+        let stretch = pos1.distance(pos2) - bond.distance;
+        return axis * (bonding.stiffness * stretch + bonding.damping * (vel2 - vel1).dot(axis));
+    }
+
+    fn apply_force(&mut self, handle: RigidBodyHandle, force: Vec2) {
+        if let Some(body) = self.rigid_bodies.get_mut(handle) {
+            body.add_force(Vector2::new(force.x, force.y), true);
+        }
+    }
+
+    /// Runs the reaction table over all neighbor pairs and returns the bodies which changed type, with
+    /// their new types. Every particle reacts at most once per step.
+    pub fn update_reactions(&mut self) -> Vec<(RigidBodyHandle, u128)> {
+        if self.types.reactions.is_empty() {
+            return vec![];
+        }
+        if get_settings().neighbor_search == NeighborSearch::Rapier {
+            self.update_neighbors();
+        }
+        let mut reacted: HashSet<RigidBodyHandle> = HashSet::new();
+        let mut changes: Vec<(RigidBodyHandle, u128)> = vec![];
+        let (neighbors, reactions) = (&self.neighbors, &mut self.types.reactions);
+        for particle in neighbors.entries() {
+            for reaction in reactions.iter_mut().filter(|reaction| reaction.a == particle.p_type as usize) {
+                if reacted.contains(&particle.handle) {
+                    break;
+                }
+                neighbors.for_each_in_range(particle.pos, reaction.distance, |neighbor| {
+                    if reacted.contains(&particle.handle) || neighbor.handle == particle.handle || neighbor.p_type as usize != reaction.b || reacted.contains(&neighbor.handle) {
+                        return;
+                    }
+                    if rand::gen_range(0.0, 1.0) < reaction.probability {
+                        reacted.insert(particle.handle);
+                        reacted.insert(neighbor.handle);
+                        changes.push((particle.handle, reaction.into_a as u128));
+                        changes.push((neighbor.handle, reaction.into_b as u128));
+                        reaction.count += 1;
+                    }
+                });
+            }
+        }
+        return changes;
+    }
+
+    pub fn bond_stats(&self) -> BondStats {
+        return self.bond_stats;
+    }
+
+    pub fn bonds(&self) -> &[Bond] {
+        return &self.bonds;
+    }
+
+    /// Bodies joined by every bond.
+    pub fn bonded_pairs(&self) -> Vec<(RigidBodyHandle, RigidBodyHandle)> {
+        return self.bonds.iter().map(|bond| (bond.body1, bond.body2)).collect();
+    }
+
     pub fn remove_physics_object(&mut self, body_handle: RigidBodyHandle) {
         _ = self.rigid_bodies.remove(body_handle, &mut self.island_manager, &mut self.colliders, &mut self.impulse_joint_set, &mut self.multibody_joint_set, true, );
     }
@@ -181,8 +456,10 @@ impl Physics {
         return self.types.get_type(id);
     }
 
-    fn get_in_range(&self, rbh: RigidBodyHandle, pos: &Vec2, radius: f32) -> Vec<RigidBodyHandle> {
-        let mut particles: Vec<RigidBodyHandle> = vec![];
+    /// Positions and types of the particles whose centers are within `radius`, the same range the grid
+    /// search uses (the shape query alone also reports particles just touching the edge).
+    fn get_in_range(&self, rbh: RigidBodyHandle, pos: &Vec2, radius: f32) -> Vec<(Vec2, u128)> {
+        let mut particles: Vec<(Vec2, u128)> = vec![];
         let field = Ball::new(radius);
         let location = make_isometry(pos.x, pos.y, 0.0);
         let filter = QueryFilter {
@@ -194,68 +471,102 @@ impl Physics {
         };
         self.query_pipeline.intersections_with_shape(&self.rigid_bodies, &self.colliders, &location, &field, filter,
             |collided| {
-                self.colliders.get(collided).inspect(|collider| {
-                    collider.parent().inspect(|rbh2| {
-                        particles.push(rbh2.clone());
-                    });
-                });
+                let body = self.colliders.get(collided).and_then(|collider| collider.parent()).and_then(|rbh2| self.rigid_bodies.get(rbh2));
+                if let Some(body) = body {
+                    let pos2 = matrix_to_vec2(body.position().translation);
+                    if pos2.distance_squared(*pos) <= radius * radius {
+                        particles.push((pos2, body.user_data));
+                    }
+                }
                 return true;
         });
         return particles;
     }
 
+    /// Field force from a rapier shape query, used by `NeighborSearch::Rapier`. The grid search computes
+    /// all forces at once in `update_field_forces`.
     pub fn field_react(&mut self, position: Vec2, _size: f32, p_type: u128, handle: RigidBodyHandle) {
         let settings = get_settings();
         let particle_type0 = self.types.get_type(p_type);
-        //let f0 = particle_type0.actions.get(p_type as usize).unwrap();
         let field_radius = particle_type0.get_field_range() * settings.field;
-        //let radius = settings.field * 0.5 + settings.field * f0;
-        let force = settings.force;
-        let repel = settings.repel;
-        let iso0 = make_isometry(position.x, position.y, 0.0);
-        let field = Ball::new(field_radius);
-        let filter = QueryFilter {
-            flags: QueryFilterFlags::ONLY_DYNAMIC | QueryFilterFlags::EXCLUDE_SENSORS,
-            groups: None,
-            exclude_collider: None,
-            exclude_rigid_body: Some(handle),
-            ..Default::default()
-        };
         let mut impulse = Vec2::ZERO;
-        self.query_pipeline.intersections_with_shape(&self.rigid_bodies, &self.colliders, &iso0, &field, filter,
-            |collided| {
-                let particle1_collider = self.colliders.get(collided).unwrap();
-                let particle1_handle = particle1_collider.parent().unwrap();
-                let particle1 = self.rigid_bodies.get(particle1_handle).unwrap();
-                let f = force;
-                let t1 = particle1.user_data;
-                let a = particle_type0.get_action(t1 as usize);
-                let pos2 = matrix_to_vec2(particle1.position().translation);
-                let dist = position.distance(pos2);
-                let rel_dist = dist/field_radius;
-                let mut scalar = 0.0;
-                let vector = (pos2 - position).normalize_or_zero();
-                if rel_dist > repel && rel_dist != 0.0 {
-                    scalar = (f * a) / rel_dist;
-                } else if rel_dist > repel/2.0 {
-                    if !settings.repel_on {
-                        scalar = 0.0;
-                    } else {
-                        scalar = -(f * a) / rel_dist/repel;
-                    }
-                } else {
-                    scalar = -f;
-                }
-                impulse += vector * scalar;
-                return true;
-            },
-        );
+        for (pos2, p_type2) in self.get_in_range(handle, &position, field_radius) {
+            impulse += pair_force(&settings, particle_type0, field_radius, position, pos2, p_type2);
+        }
         let particle0 = self.rigid_bodies.get_mut(handle).unwrap();
         particle0.reset_forces(true);
         particle0.add_force(Vector2::new(impulse.x, impulse.y), true);
     }
 
+    /// Number of particles inside the field range of the given one.
+    pub fn count_neighbors(&self, handle: RigidBodyHandle, position: Vec2, p_type: u128) -> usize {
+        let settings = get_settings();
+        let field_radius = self.types.get_type(p_type).get_field_range() * settings.field;
+        return match settings.neighbor_search {
+            NeighborSearch::Grid => {
+                let mut count = 0;
+                self.neighbors.for_each_in_range(position, field_radius, |neighbor| {
+                    if neighbor.handle != handle {
+                        count += 1;
+                    }
+                });
+                count
+            },
+            NeighborSearch::Rapier => self.get_in_range(handle, &position, field_radius).len(),
+        };
+    }
+
+    /// Field forces for every particle in the neighbor grid. Forces are accumulated on a pool of
+    /// `settings.threads` workers which only read the grid and the types, then applied to the bodies
+    /// on this thread. A panic in a worker is resumed here with its own message.
+    pub fn update_field_forces(&mut self, settings: &Settings) {
+        let threads = settings.threads.max(1);
+        if threads == 1 {
+            self.workers = None;
+        } else if self.workers.as_ref().map(|pool| pool.current_num_threads()) != Some(threads) {
+            self.workers = match ThreadPoolBuilder::new().num_threads(threads).thread_name(|i| format!("field-{}", i)).build() {
+                Ok(pool) => Some(pool),
+                Err(e) => {
+                    warn!("can't start {} field force threads, running on one: {}", threads, e);
+                    None
+                },
+            };
+        }
+        let entries = self.neighbors.entries();
+        let (neighbors, types) = (&self.neighbors, &self.types);
+        let force = |particle: &NeighborEntry| (particle.handle, grid_field_force(neighbors, types, settings, particle));
+        let forces: Vec<(RigidBodyHandle, Vec2)> = match &self.workers {
+            Some(pool) if entries.len() >= 2 * MIN_PARTICLES_PER_TASK => {
+                pool.install(|| entries.par_iter().with_min_len(MIN_PARTICLES_PER_TASK).map(force).collect())
+            },
+            _ => entries.iter().map(force).collect(),
+        };
+        for (handle, force) in forces {
+            if let Some(body) = self.rigid_bodies.get_mut(handle) {
+                body.reset_forces(true);
+                body.add_force(Vector2::new(force.x, force.y), true);
+            }
+        }
+    }
+
+    /// Rebuilds the neighbor grid from current body positions. The cell size is the largest field range,
+    /// so a query never has to look further than the neighboring cells.
+    pub fn update_neighbors(&mut self) {
+        let settings = get_settings();
+        let max_field = self.types.types.values().map(|t| t.get_field_range()).fold(0.0, f32::max);
+        let entries = self.rigid_bodies.iter().map(|(handle, body)| NeighborEntry {
+            handle,
+            pos: matrix_to_vec2(body.position().translation),
+            p_type: body.user_data,
+        });
+        let period = settings.boundary.period(settings.world_radius);
+        self.neighbors.rebuild(max_field * settings.field, period, entries);
+    }
+
     pub fn step_physics(&mut self) {
+        self.apply_solver(&get_settings().solver);
+        self.sync_collisions();
+        self.update_bonds();
         self.physics_pipeline.step(
             &self.gravity,
             &self.integration_parameters,
@@ -271,6 +582,9 @@ impl Physics {
             &self.physics_hooks,
             &self.event_handler,
         );
+        if get_settings().neighbor_search == NeighborSearch::Grid {
+            self.update_neighbors();
+        }
     }
 
     fn iso_to_vec2_rot(&self, isometry: &Isometry<Real>) -> (Vec2, f32) {
@@ -313,6 +627,26 @@ impl Physics {
         }
     }
 
+    pub fn get_object_velocity(&self, handle: RigidBodyHandle) -> Option<(Vec2, f32)> {
+        return self.rigid_bodies.get(handle).map(|body| {
+            let v = body.linvel();
+            (Vec2::new(v.x, v.y), body.angvel())
+        });
+    }
+
+    pub fn apply_impulse(&mut self, handle: RigidBodyHandle, impulse: Vec2) {
+        if let Some(body) = self.rigid_bodies.get_mut(handle) {
+            body.apply_impulse(Vector2::new(impulse.x, impulse.y), true);
+        }
+    }
+
+    pub fn set_object_velocity(&mut self, handle: RigidBodyHandle, lin_vel: Vec2, ang_vel: f32) {
+        if let Some(body) = self.rigid_bodies.get_mut(handle) {
+            body.set_linvel(Vector2::new(lin_vel.x, lin_vel.y), true);
+            body.set_angvel(ang_vel, true);
+        }
+    }
+
     fn get_body_handle_from_collider(&self, collider_handle: ColliderHandle) -> Option<RigidBodyHandle> {
         let collider: &Collider;
         match self.colliders.get(collider_handle) {
@@ -345,5 +679,25 @@ impl Physics {
     }
 }
 
+/// Force on a particle of type `particle_type0` at `position` from a single neighbor of type `t1` at `pos2`.
+fn pair_force(settings: &Settings, particle_type0: &PhysicsType, field_radius: f32, position: Vec2, pos2: Vec2, t1: u128) -> Vec2 {
+    let a = particle_type0.get_action(t1 as usize);
+    let dist = position.distance(pos2);
+    let rel_dist = dist/field_radius;
+    let vector = (pos2 - position).normalize_or_zero();
+    let scalar = settings.force * settings.force_law.scalar(rel_dist, a, settings.repel, settings.repel_on);
+    return vector * scalar;
+}
 
-
+/// Read-only sum of all field forces acting on `particle`, safe to call from worker threads.
+fn grid_field_force(neighbors: &SpatialHash, types: &PhysicsTypes, settings: &Settings, particle: &NeighborEntry) -> Vec2 {
+    let particle_type0 = types.get_type(particle.p_type);
+    let field_radius = particle_type0.get_field_range() * settings.field;
+    let mut impulse = Vec2::ZERO;
+    neighbors.for_each_in_range(particle.pos, field_radius, |neighbor| {
+        if neighbor.handle != particle.handle {
+            impulse += pair_force(settings, particle_type0, field_radius, particle.pos, neighbor.pos, neighbor.p_type);
+        }
+    });
+    return impulse;
+}
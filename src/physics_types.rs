@@ -1,24 +1,35 @@
 use std::collections::HashMap;
-use macroquad::color::colors;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+use macroquad::color::{colors, hsl_to_rgb};
 use macroquad::rand::rand;
 use macroquad::{color::Color, rand}; 
 use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
 use crate::globals::*;
+use crate::reaction::*;
+
+pub const RULES_VERSION: u32 = 1;
+/// Rapier has 32 interaction groups, types above that share the group `type % 32`.
+pub const COLLISION_GROUPS: usize = 32;
+/// Longest bond distance the matrix editor and the randomizer go up to.
+pub const MAX_BOND_DISTANCE: f32 = 30.0;
 
 
 pub struct PhysicsType {
     type_id: u128,
-    actions: [f32; TYPES_NUM as usize],
+    actions: Vec<f32>,
     color: Color,
     field: f32,
 }
 
 impl PhysicsType {
-    pub fn new(type_id: u128, color: Color) -> Self {
-        let mut actions: [f32; TYPES_NUM as usize] = [0.0; TYPES_NUM as usize];
-        for i in 0..TYPES_NUM as usize {
+    pub fn new(type_id: u128, color: Color, types_num: usize) -> Self {
+        let mut actions: Vec<f32> = vec![0.0; types_num];
+        for i in 0..types_num {
             let mut a: f32 = 0.0;
-            if i == TYPES_NUM-1 && type_id as usize == TYPES_NUM-1 {
+            if i == types_num-1 && type_id as usize == types_num-1 {
 
             } else {
                 a = rand::gen_range(-1.0, 1.0);
@@ -38,7 +49,17 @@ impl PhysicsType {
     }
 
     pub fn get_action(&self, id: usize) -> f32 {
-        return self.actions[id];
+        return self.actions.get(id).copied().unwrap_or(0.0);
+    }
+
+    pub fn get_color(&self) -> Color {
+        return self.color;
+    }
+
+    pub fn set_action(&mut self, id: usize, action: f32) {
+        if let Some(a) = self.actions.get_mut(id) {
+            *a = action.clamp(-1.0, 1.0);
+        }
     }
 
 
@@ -47,49 +68,236 @@ impl PhysicsType {
 pub struct PhysicsTypes {
     pub types: HashMap<u128, PhysicsType>,
     pub colors: Vec<Color>,
+    /// Bit mask of the collision groups every group collides with, kept symmetric.
+    pub collisions: Vec<u32>,
+    /// Symmetric matrix of distances under which two particles bond, zero means the pair never bonds.
+    pub bonds: Vec<Vec<f32>>,
+    /// Transmutation rules, evaluated in order every step.
+    pub reactions: Vec<Reaction>,
 }
 
 impl PhysicsTypes {
 
     pub fn random() -> Self {
         let mut types: HashMap<u128, PhysicsType> = HashMap::new();
-        let num = get_settings().particles_num;
+        let num = get_settings().particle_types.max(1);
         //let colors = vec![RED, GREEN, BLUE, YELLOW, ORANGE, MAGENTA, DARKGREEN, PURPLE, PINK, VIOLET, DARKBLUE, WHITE, SKYBLUE, LIME, DARKPURPLE, BROWN, DARKBROWN, DARKGRAY, LIGHTGRAY ];
         let colors = Self::generate_colors(num);
-        for n in 0..colors.len()-1 {
+        for n in 0..colors.len() {
             //let action: f32 = rand::gen_range(-1.0, 1.0);
             let type_id = n as u128;
             let color = colors[n];
-            let t = PhysicsType::new(type_id, color);
+            let t = PhysicsType::new(type_id, color, num);
             types.insert(type_id, t);
         }
-        Self { types, colors }
+        let collisions = vec![u32::MAX; num.min(COLLISION_GROUPS)];
+        let bonds = vec![vec![0.0; num]; num];
+        Self { types, colors, collisions, bonds, reactions: vec![] }
     }
 
     pub fn get_type(&self, id: u128) -> &PhysicsType {
         return self.types.get(&id).unwrap();
     }
 
+    pub fn types_num(&self) -> usize {
+        return self.types.len();
+    }
+
+    pub fn set_action(&mut self, from: usize, to: usize, action: f32) {
+        if let Some(t) = self.types.get_mut(&(from as u128)) {
+            t.set_action(to, action);
+        }
+    }
+
+    pub fn collision_group(id: usize) -> usize {
+        return id % COLLISION_GROUPS;
+    }
+
+    pub fn collision_filter(&self, group: usize) -> u32 {
+        return self.collisions.get(group).copied().unwrap_or(u32::MAX);
+    }
+
+    pub fn collides(&self, a: usize, b: usize) -> bool {
+        let (ga, gb) = (Self::collision_group(a), Self::collision_group(b));
+        return self.collision_filter(ga) & (1 << gb) != 0;
+    }
+
+    pub fn set_collides(&mut self, a: usize, b: usize, collide: bool) {
+        let (ga, gb) = (Self::collision_group(a), Self::collision_group(b));
+        for (from, to) in [(ga, gb), (gb, ga)] {
+            if let Some(filter) = self.collisions.get_mut(from) {
+                if collide {
+                    *filter |= 1 << to;
+                } else {
+                    *filter &= !(1 << to);
+                }
+            }
+        }
+    }
+
+    pub fn bond_distance(&self, a: usize, b: usize) -> f32 {
+        return self.bonds.get(a).and_then(|row| row.get(b)).copied().unwrap_or(0.0);
+    }
+
+    pub fn set_bond_distance(&mut self, a: usize, b: usize, distance: f32) {
+        let distance = distance.clamp(0.0, MAX_BOND_DISTANCE);
+        for (from, to) in [(a, b), (b, a)] {
+            if let Some(d) = self.bonds.get_mut(from).and_then(|row| row.get_mut(to)) {
+                *d = distance;
+            }
+        }
+    }
+
+    /// Longest bond distance of the type to any other, the range to look for bonding partners in.
+    pub fn max_bond_distance(&self, id: usize) -> f32 {
+        return self.bonds.get(id).map(|row| row.iter().copied().fold(0.0, f32::max)).unwrap_or(0.0);
+    }
+
+    /// With `random` about half of the pairs get a random bond distance, the rest never bond.
+    pub fn fill_bonds(&mut self, random: bool) {
+        let n = self.types_num();
+        for a in 0..n {
+            for b in a..n {
+                let distance = match random && rand::gen_range(0.0, 1.0) < 0.5 {
+                    true => rand::gen_range(0.2, 1.0) * MAX_BOND_DISTANCE,
+                    false => 0.0,
+                };
+                self.set_bond_distance(a, b, distance);
+            }
+        }
+    }
+
+    pub fn fill_row(&mut self, from: usize, random: bool) {
+        for to in 0..self.types_num() {
+            let action = if random { rand::gen_range(-1.0, 1.0) } else { 0.0 };
+            self.set_action(from, to, action);
+        }
+    }
+
+    pub fn fill_column(&mut self, to: usize, random: bool) {
+        for from in 0..self.types_num() {
+            let action = if random { rand::gen_range(-1.0, 1.0) } else { 0.0 };
+            self.set_action(from, to, action);
+        }
+    }
+
+    pub fn to_rule_set(&self) -> RuleSet {
+        let types_num = self.types_num();
+        let mut actions: Vec<Vec<f32>> = vec![];
+        let mut fields: Vec<f32> = vec![];
+        let mut colors: Vec<[f32; 4]> = vec![];
+        for id in 0..types_num {
+            let t = self.get_type(id as u128);
+            actions.push(t.actions.clone());
+            fields.push(t.field);
+            colors.push([t.color.r, t.color.g, t.color.b, t.color.a]);
+        }
+        return RuleSet { version: RULES_VERSION, types_num, actions, fields, colors, collisions: self.collisions.clone(), bonds: self.bonds.clone(), reactions: self.reactions.clone() };
+    }
+
+    pub fn from_rule_set(rules: &RuleSet) -> io::Result<Self> {
+        rules.validate(get_settings().max_types)?;
+        let mut types: HashMap<u128, PhysicsType> = HashMap::new();
+        let mut colors: Vec<Color> = vec![];
+        for n in 0..rules.types_num {
+            let [r, g, b, a] = rules.colors[n];
+            let color = Color::new(r, g, b, a);
+            let actions = rules.actions[n].clone();
+            let t = PhysicsType { type_id: n as u128, actions, color, field: rules.fields[n] };
+            types.insert(n as u128, t);
+            colors.push(color);
+        }
+        let collisions = match rules.collisions.is_empty() {
+            true => vec![u32::MAX; rules.types_num.min(COLLISION_GROUPS)],
+            false => rules.collisions.clone(),
+        };
+        let bonds = match rules.bonds.is_empty() {
+            true => vec![vec![0.0; rules.types_num]; rules.types_num],
+            false => rules.bonds.clone(),
+        };
+        Ok(Self { types, colors, collisions, bonds, reactions: rules.reactions.clone() })
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(&self.to_rule_set())?;
+        fs::write(path, json)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let json = fs::read_to_string(path)?;
+        let rules: RuleSet = serde_json::from_str(&json)?;
+        Self::from_rule_set(&rules)
+    }
+
+    /// `n` hues spread evenly around the color wheel, starting from a random offset.
     fn generate_colors(n: usize) -> Vec<Color> {
         let mut colors: Vec<Color> = Vec::new();
-        let root = (n as f64).powf(1.0/3.0) as i32;
-        //let step = (1.0/root) as f32;
-
-        for _ in 0..n {
-            let ir = rand::gen_range(0, root);
-            let ig = rand::gen_range(0, root);
-            let ib = rand::gen_range(0, root);
-            let r = ir as f32 / (root as f32);
-            let g = ig as f32 / (root as f32);
-            let b = ib as f32 / (root as f32);
-            let color = Color::new(r, g, b, 1.0);
-            colors.push(color);
+        let offset = rand::gen_range(0.0, 1.0);
+        for i in 0..n {
+            let hue = (offset + i as f32 / n as f32) % 1.0;
+            let lightness = if i % 2 == 0 { 0.55 } else { 0.7 };
+            colors.push(hsl_to_rgb(hue, 0.85, lightness));
         }
         return colors;
     }
 
 }
 
+//?         [[[RULE_SET]]]
+/// Serialized form of [`PhysicsTypes`]: the interaction matrix (`actions[from][to]`),
+/// field range multiplier and color of every particle type.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RuleSet {
+    pub version: u32,
+    pub types_num: usize,
+    pub actions: Vec<Vec<f32>>,
+    pub fields: Vec<f32>,
+    pub colors: Vec<[f32; 4]>,
+    /// Collision filter of every collision group, empty means everything collides.
+    #[serde(default)]
+    pub collisions: Vec<u32>,
+    /// Bond distance matrix, empty means no bonds.
+    #[serde(default)]
+    pub bonds: Vec<Vec<f32>>,
+    #[serde(default)]
+    pub reactions: Vec<Reaction>,
+}
+
+impl RuleSet {
+    /// `max_types` is `Settings::max_types`, passed in so the checks don't depend on the global settings.
+    fn validate(&self, max_types: usize) -> io::Result<()> {
+        let invalid = |msg: String| io::Error::new(ErrorKind::InvalidData, msg);
+        if self.version > RULES_VERSION {
+            return Err(invalid(format!("unsupported rules version {} (max {})", self.version, RULES_VERSION)));
+        }
+        if self.types_num == 0 {
+            return Err(invalid("rule set has no particle types".to_string()));
+        }
+        if self.types_num > max_types {
+            return Err(invalid(format!("rule set has {} particle types (max types is {})", self.types_num, max_types)));
+        }
+        if self.actions.len() != self.types_num || self.fields.len() != self.types_num || self.colors.len() != self.types_num {
+            return Err(invalid(format!("rule set tables don't match types number {}", self.types_num)));
+        }
+        if self.actions.iter().any(|row| row.len() != self.types_num) {
+            return Err(invalid("interaction matrix is not square".to_string()));
+        }
+        if !self.collisions.is_empty() && self.collisions.len() != self.types_num.min(COLLISION_GROUPS) {
+            return Err(invalid("collision matrix doesn't match types number".to_string()));
+        }
+        if !self.bonds.is_empty() && (self.bonds.len() != self.types_num || self.bonds.iter().any(|row| row.len() != self.types_num)) {
+            return Err(invalid("bond matrix doesn't match types number".to_string()));
+        }
+        if let Some(reaction) = self.reactions.iter().find(|reaction| !reaction.is_valid(self.types_num)) {
+            return Err(invalid(format!("invalid reaction {}", reaction.name())));
+        }
+        Ok(())
+    }
+}
+
 pub struct PhysicsProperties {
     pub friction: f32,
     pub restitution: f32,
@@ -125,4 +333,76 @@ pub struct PhysicsData {
     pub rotation: f32,
     pub mass: f32,
     pub kin_eng: Option<f32>,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX: usize = 100;
+
+    fn rule_set(types_num: usize) -> RuleSet {
+        return RuleSet {
+            version: RULES_VERSION,
+            types_num,
+            actions: (0..types_num).map(|from| (0..types_num).map(|to| (from as f32 - to as f32) / types_num as f32).collect()).collect(),
+            fields: vec![1.0; types_num],
+            colors: vec![[0.5, 0.25, 1.0, 1.0]; types_num],
+            collisions: vec![],
+            bonds: vec![],
+            reactions: vec![],
+        };
+    }
+
+    #[test]
+    fn rule_set_round_trips_through_json() {
+        let mut rules = rule_set(3);
+        rules.collisions = vec![0b011, 0b011, 0b100];
+        rules.bonds = vec![vec![0.0, 5.0, 0.0], vec![5.0, 0.0, 0.0], vec![0.0, 0.0, 12.5]];
+        rules.reactions = vec![Reaction::catalysis(0, 1, 2)];
+        let json = serde_json::to_string(&rules).unwrap();
+        let loaded: RuleSet = serde_json::from_str(&json).unwrap();
+        assert!(loaded.validate(MAX).is_ok());
+        assert_eq!(loaded.version, rules.version);
+        assert_eq!(loaded.types_num, rules.types_num);
+        assert_eq!(loaded.actions, rules.actions);
+        assert_eq!(loaded.fields, rules.fields);
+        assert_eq!(loaded.colors, rules.colors);
+        assert_eq!(loaded.collisions, rules.collisions);
+        assert_eq!(loaded.bonds, rules.bonds);
+        assert_eq!(loaded.reactions[0].name(), rules.reactions[0].name());
+    }
+
+    #[test]
+    fn rule_set_without_optional_tables_loads() {
+        let json = r#"{"version":1,"types_num":2,"actions":[[0.1,-0.2],[0.3,0.4]],"fields":[1.0,1.2],"colors":[[1,0,0,1],[0,1,0,1]]}"#;
+        let rules: RuleSet = serde_json::from_str(json).unwrap();
+        assert!(rules.validate(MAX).is_ok());
+        assert!(rules.collisions.is_empty() && rules.bonds.is_empty() && rules.reactions.is_empty());
+    }
+
+    #[test]
+    fn validate_rejects_broken_rule_sets() {
+        assert!(rule_set(MAX).validate(MAX).is_ok());
+        assert!(rule_set(0).validate(MAX).is_err());
+        assert!(rule_set(MAX + 1).validate(MAX).is_err());
+        let mut rules = rule_set(3);
+        rules.version = RULES_VERSION + 1;
+        assert!(rules.validate(MAX).is_err());
+        let mut rules = rule_set(3);
+        rules.actions[1].pop();
+        assert!(rules.validate(MAX).is_err());
+        let mut rules = rule_set(3);
+        rules.fields.pop();
+        assert!(rules.validate(MAX).is_err());
+        let mut rules = rule_set(3);
+        rules.collisions = vec![u32::MAX; 2];
+        assert!(rules.validate(MAX).is_err());
+        let mut rules = rule_set(3);
+        rules.bonds = vec![vec![0.0; 3]; 2];
+        assert!(rules.validate(MAX).is_err());
+        let mut rules = rule_set(3);
+        rules.reactions = vec![Reaction::catalysis(0, 3, 1)];
+        assert!(rules.validate(MAX).is_err());
+    }
+}
@@ -0,0 +1,154 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+use macroquad::color::hsl_to_rgb;
+use serde::{Deserialize, Serialize};
+use crate::boundary::*;
+use crate::force_law::*;
+use crate::globals::*;
+use crate::physics_types::*;
+use crate::reaction::*;
+use crate::spawn_layout::*;
+
+pub const PRESET_VERSION: u32 = 1;
+
+//?         [[[PRESET]]]
+/// Named starting point: rule set plus the settings (force law, boundary, spawn layout, particle
+/// counts...) it was tuned for.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Preset {
+    pub version: u32,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub settings: Settings,
+    pub rules: RuleSet,
+}
+
+impl Preset {
+
+    pub fn new(name: &str, description: &str, settings: Settings, rules: RuleSet) -> Self {
+        return Self { version: PRESET_VERSION, name: name.to_string(), description: description.to_string(), settings, rules };
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let json = fs::read_to_string(path)?;
+        let preset: Preset = serde_json::from_str(&json)?;
+        if preset.version > PRESET_VERSION {
+            return Err(io::Error::new(ErrorKind::InvalidData, format!("unsupported preset version {} (max {})", preset.version, PRESET_VERSION)));
+        }
+        PhysicsTypes::from_rule_set(&preset.rules)?;
+        return Ok(preset);
+    }
+
+    /// Presets shipped with the app.
+    pub fn builtin() -> Vec<Preset> {
+        return vec![
+            Self::from_matrix("cells", "Self-attracting cores wrapped in a layer of a second type.",
+                &[
+                    &[0.8, -0.3, 0.2],
+                    &[0.6, 0.5, -0.4],
+                    &[-0.2, 0.7, 0.3],
+                ],
+                |s| {
+                    s.particles_num = 2400;
+                    s.force_law = ForceLaw::ParticleLife { beta: 0.3 };
+                    s.layout = SpawnLayout::Disk;
+                }),
+            Self::from_matrix("worms", "Every type follows the next one and flees the previous, particles line up into chains.",
+                &[
+                    &[0.3, 0.8, 0.0, -0.4],
+                    &[-0.4, 0.3, 0.8, 0.0],
+                    &[0.0, -0.4, 0.3, 0.8],
+                    &[0.8, 0.0, -0.4, 0.3],
+                ],
+                |s| {
+                    s.particles_num = 2000;
+                    s.force_law = ForceLaw::ParticleLife { beta: 0.3 };
+                    s.boundary = Boundary::Torus;
+                    s.world_radius = 3000.0;
+                }),
+            Self::from_matrix("crystal", "Two mutually bound types repelling their own kind settle into a lattice.",
+                &[
+                    &[-0.3, 1.0],
+                    &[1.0, -0.3],
+                ],
+                |s| {
+                    s.particles_num = 1200;
+                    s.force_law = ForceLaw::LennardJones { sigma: 0.3, epsilon: 0.05 };
+                    s.damping = 2.0;
+                    s.layout = SpawnLayout::Center;
+                }),
+            Self::from_matrix("chasers", "Hunters are drawn to the prey, which runs away from them.",
+                &[
+                    &[0.2, 0.9],
+                    &[-0.9, 0.2],
+                ],
+                |s| {
+                    s.particles_num = 1500;
+                    s.force_law = ForceLaw::ParticleLife { beta: 0.3 };
+                    s.layout = SpawnLayout::Blobs { count: 4 };
+                }),
+            Self::from_matrix("membrane", "A dense blob of one type coated with a thin shell of the other.",
+                &[
+                    &[0.9, 0.1],
+                    &[0.6, -0.2],
+                ],
+                |s| {
+                    s.particles_num = 1800;
+                    s.force_law = ForceLaw::Gaussian { core: 0.3, width: 0.15 };
+                    s.layout = SpawnLayout::Center;
+                }),
+            Self::from_matrix("autocatalysis", "Rock-paper-scissors chemistry: every type converts the one it chases, waves of color sweep the world.",
+                &[
+                    &[0.2, 0.6, -0.3],
+                    &[-0.3, 0.2, 0.6],
+                    &[0.6, -0.3, 0.2],
+                ],
+                |s| {
+                    s.particles_num = 2400;
+                    s.force_law = ForceLaw::ParticleLife { beta: 0.3 };
+                    s.boundary = Boundary::Torus;
+                }).with_reactions(vec![
+                    Reaction::catalysis(1, 0, 0),
+                    Reaction::catalysis(2, 1, 1),
+                    Reaction::catalysis(0, 2, 2),
+                ]),
+        ];
+    }
+
+    fn with_reactions(mut self, reactions: Vec<Reaction>) -> Self {
+        self.rules.reactions = reactions;
+        return self;
+    }
+
+    /// Built-in preset from the interaction matrix (`matrix[from][to]`) and tweaks of the default settings.
+    fn from_matrix(name: &str, description: &str, matrix: &[&[f32]], tweak: impl Fn(&mut Settings)) -> Self {
+        let types_num = matrix.len();
+        let mut settings = Settings { particle_types: types_num, ..Default::default() };
+        tweak(&mut settings);
+        let colors = (0..types_num).map(|i| {
+            let color = hsl_to_rgb(i as f32 / types_num as f32, 0.85, 0.6);
+            [color.r, color.g, color.b, color.a]
+        }).collect();
+        let rules = RuleSet {
+            version: RULES_VERSION,
+            types_num,
+            actions: matrix.iter().map(|row| row.to_vec()).collect(),
+            fields: vec![1.0; types_num],
+            colors,
+            collisions: vec![],
+            bonds: vec![],
+            reactions: vec![],
+        };
+        return Self::new(name, description, settings, rules);
+    }
+}
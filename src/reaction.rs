@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+
+/// Longest reaction distance the reaction editor goes up to.
+pub const MAX_REACTION_DISTANCE: f32 = 60.0;
+
+//?         [[[REACTION]]]
+/// Transmutation rule `a + b -> into_a + into_b`: a particle of type `a` closer than `distance`
+/// to one of type `b` turns both of them into the new types with `probability` per step.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Reaction {
+    pub a: usize,
+    pub b: usize,
+    pub into_a: usize,
+    pub into_b: usize,
+    pub probability: f32,
+    pub distance: f32,
+    /// How many times the reaction happened since the rules were loaded.
+    #[serde(skip)]
+    pub count: u64,
+}
+
+impl Reaction {
+
+    pub fn new(a: usize, b: usize, into_a: usize, into_b: usize, probability: f32, distance: f32) -> Self {
+        return Self { a, b, into_a, into_b, probability, distance, count: 0 };
+    }
+
+    /// Catalysis: `b` turns `a` into `into_a` and stays itself.
+    pub fn catalysis(a: usize, b: usize, into_a: usize) -> Self {
+        return Self::new(a, b, into_a, b, 0.05, 10.0);
+    }
+
+    pub fn name(&self) -> String {
+        return format!("{} + {} -> {} + {}", self.a, self.b, self.into_a, self.into_b);
+    }
+
+    pub fn is_valid(&self, types_num: usize) -> bool {
+        return [self.a, self.b, self.into_a, self.into_b].iter().all(|t| *t < types_num)
+            && (0.0..=1.0).contains(&self.probability) && self.distance >= 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catalysis_keeps_the_catalyst() {
+        let reaction = Reaction::catalysis(0, 2, 1);
+        assert_eq!((reaction.a, reaction.b, reaction.into_a, reaction.into_b), (0, 2, 1, 2));
+        assert_eq!(reaction.name(), "0 + 2 -> 1 + 2");
+        assert!(reaction.is_valid(3));
+    }
+
+    #[test]
+    fn every_type_must_exist() {
+        assert!(Reaction::new(0, 1, 2, 3, 0.5, 10.0).is_valid(4));
+        assert!(!Reaction::new(4, 1, 2, 3, 0.5, 10.0).is_valid(4));
+        assert!(!Reaction::new(0, 4, 2, 3, 0.5, 10.0).is_valid(4));
+        assert!(!Reaction::new(0, 1, 4, 3, 0.5, 10.0).is_valid(4));
+        assert!(!Reaction::new(0, 1, 2, 4, 0.5, 10.0).is_valid(4));
+        assert!(!Reaction::catalysis(0, 0, 0).is_valid(0));
+    }
+
+    #[test]
+    fn probability_and_distance_must_be_in_range() {
+        assert!(Reaction::new(0, 1, 1, 0, 0.0, 0.0).is_valid(2));
+        assert!(Reaction::new(0, 1, 1, 0, 1.0, MAX_REACTION_DISTANCE).is_valid(2));
+        assert!(!Reaction::new(0, 1, 1, 0, -0.1, 10.0).is_valid(2));
+        assert!(!Reaction::new(0, 1, 1, 0, 1.1, 10.0).is_valid(2));
+        assert!(!Reaction::new(0, 1, 1, 0, f32::NAN, 10.0).is_valid(2));
+        assert!(!Reaction::new(0, 1, 1, 0, 0.5, -1.0).is_valid(2));
+    }
+
+    #[test]
+    fn count_is_not_saved() {
+        let mut reaction = Reaction::catalysis(0, 1, 1);
+        reaction.count = 42;
+        let json = serde_json::to_string(&reaction).unwrap();
+        assert!(!json.contains("count"));
+        let loaded: Reaction = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.count, 0);
+        assert_eq!(loaded.name(), reaction.name());
+    }
+}
@@ -0,0 +1,215 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::PathBuf;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{imageops, Delay, Frame, RgbaImage};
+use macroquad::prelude::*;
+use crate::globals::*;
+use crate::util::*;
+
+/// GIF frames are scaled down to this width, full size frames make huge and slow GIFs.
+const GIF_MAX_WIDTH: u32 = 480;
+/// Palette quantization speed of the GIF encoder, 1 (best) ..= 30 (fastest).
+const GIF_SPEED: i32 = 10;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum CaptureFormat {
+    PngSequence,
+    Gif,
+}
+
+impl CaptureFormat {
+
+    pub fn all() -> [CaptureFormat; 2] {
+        return [CaptureFormat::PngSequence, CaptureFormat::Gif];
+    }
+
+    pub fn name(&self) -> &'static str {
+        return match self {
+            CaptureFormat::PngSequence => "PNG Sequence",
+            CaptureFormat::Gif => "GIF",
+        };
+    }
+}
+
+//?         [[[RECORDER]]]
+/// Grabs every `every`-th rendered frame and writes it into `dir`,
+/// either as numbered PNG files or as frames of a single animated GIF.
+pub struct Recorder {
+    pub format: CaptureFormat,
+    pub every: u32,
+    pub dir: String,
+    recording: bool,
+    frame: u64,
+    saved: u64,
+    delay: f32,
+    out: PathBuf,
+    gif: Option<GifEncoder<BufWriter<File>>>,
+    texture: Option<Texture2D>,
+    pub last_error: Option<String>,
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Self {
+            format: CaptureFormat::PngSequence,
+            every: 1,
+            dir: CAPTURES_DIR.to_string(),
+            recording: false,
+            frame: 0,
+            saved: 0,
+            delay: 0.0,
+            out: PathBuf::new(),
+            gif: None,
+            texture: None,
+            last_error: None,
+        }
+    }
+}
+
+impl Recorder {
+
+    pub fn is_recording(&self) -> bool {
+        return self.recording;
+    }
+
+    pub fn saved_frames(&self) -> u64 {
+        return self.saved;
+    }
+
+    /// Folder of the PNG sequence or path of the GIF file being written.
+    pub fn output(&self) -> &PathBuf {
+        return &self.out;
+    }
+
+    pub fn toggle(&mut self) {
+        if self.recording {
+            self.stop();
+        } else if let Err(e) = self.start() {
+            warn!("can't start recording: {}", e);
+            self.last_error = Some(e.to_string());
+        }
+    }
+
+    pub fn start(&mut self) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        match self.format {
+            CaptureFormat::PngSequence => {
+                self.out = timestamped_path(&self.dir, "capture", "png").with_extension("");
+                fs::create_dir_all(&self.out)?;
+            },
+            CaptureFormat::Gif => {
+                self.out = timestamped_path(&self.dir, "capture", "gif");
+                let mut encoder = GifEncoder::new_with_speed(BufWriter::new(File::create(&self.out)?), GIF_SPEED);
+                encoder.set_repeat(Repeat::Infinite).map_err(io::Error::other)?;
+                self.gif = Some(encoder);
+            },
+        }
+        self.every = self.every.max(1);
+        self.frame = 0;
+        self.saved = 0;
+        self.delay = 0.0;
+        self.last_error = None;
+        self.recording = true;
+        info!("recording to {}", self.out.display());
+        return Ok(());
+    }
+
+    /// Finishes the recording, dropping the GIF encoder writes the file trailer.
+    pub fn stop(&mut self) {
+        if !self.recording {
+            return;
+        }
+        self.recording = false;
+        self.gif = None;
+        info!("recording stopped, {} frames saved to {}", self.saved, self.out.display());
+    }
+
+    /// Call after everything that should end up in the recording has been drawn.
+    pub fn capture_frame(&mut self) {
+        if !self.recording {
+            return;
+        }
+        self.delay += get_frame_time();
+        self.frame += 1;
+        if !(self.frame - 1).is_multiple_of(self.every.max(1) as u64) {
+            return;
+        }
+        let image = self.grab_screen();
+        if let Err(e) = self.write_frame(image) {
+            warn!("can't write frame {}: {}", self.saved, e);
+            self.last_error = Some(e.to_string());
+            self.stop();
+            return;
+        }
+        self.saved += 1;
+        self.delay = 0.0;
+    }
+
+    /// Reads back the framebuffer into a reused texture (a new one each frame would leak GPU memory).
+    /// The framebuffer is in physical pixels, `screen_width()` is in logical ones on HiDPI displays.
+    fn grab_screen(&mut self) -> RgbaImage {
+        let dpi_scale = unsafe {
+            let mut gl = get_internal_gl();
+            gl.flush();
+            gl.quad_context.dpi_scale()
+        };
+        let (w, h) = ((screen_width() * dpi_scale) as u16, (screen_height() * dpi_scale) as u16);
+        let texture = match self.texture {
+            Some(texture) if texture.width() as u16 == w && texture.height() as u16 == h => texture,
+            _ => {
+                if let Some(old) = self.texture.take() {
+                    old.delete();
+                }
+                let texture = Texture2D::from_rgba8(w, h, &vec![0; w as usize * h as usize * 4]);
+                self.texture = Some(texture);
+                texture
+            },
+        };
+        texture.grab_screen();
+        let data = texture.get_texture_data();
+        let mut image = RgbaImage::from_raw(data.width as u32, data.height as u32, data.bytes).unwrap();
+        imageops::flip_vertical_in_place(&mut image);
+        for pixel in image.pixels_mut() {
+            pixel[3] = 255;
+        }
+        return image;
+    }
+
+    fn write_frame(&mut self, image: RgbaImage) -> io::Result<()> {
+        match self.format {
+            CaptureFormat::PngSequence => {
+                let path = self.out.join(format!("frame_{:06}.png", self.saved));
+                image.save(&path).map_err(io::Error::other)?;
+            },
+            CaptureFormat::Gif => {
+                let image = if image.width() > GIF_MAX_WIDTH {
+                    let h = image.height() * GIF_MAX_WIDTH / image.width();
+                    imageops::resize(&image, GIF_MAX_WIDTH, h, imageops::FilterType::Triangle)
+                } else {
+                    image
+                };
+                let delay = Delay::from_numer_denom_ms((self.delay * 1000.0).round().max(10.0) as u32, 1);
+                if let Some(encoder) = self.gif.as_mut() {
+                    encoder.encode_frame(Frame::from_parts(image, 0, 0, delay)).map_err(io::Error::other)?;
+                }
+            },
+        }
+        return Ok(());
+    }
+
+    /// Blinking REC label in the screen corner, drawn after the capture so it doesn't end up in the frames.
+    pub fn draw_indicator(&self, font: &Font) {
+        if !self.recording {
+            return;
+        }
+        set_default_camera();
+        let x = screen_width() - 110.0;
+        let y = 45.0;
+        if (get_time() * 2.0) as i64 % 2 == 0 {
+            draw_circle(x, y, 8.0, RED);
+        }
+        let params = TextParams { font: *font, font_size: 16, color: RED, ..Default::default() };
+        draw_text_ex(&format!("REC {}", self.saved), x + 14.0, y + 6.0, params);
+    }
+}
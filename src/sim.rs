@@ -1,4 +1,5 @@
 #![allow(unused)]
+use crate::brush::*;
 use crate::element::*;
 use crate::camera::*;
 use crate::globals::*;
@@ -7,11 +8,20 @@ use crate::timer::Timer;
 use crate::ui::*;
 use crate::util::*;
 use crate::physics::*;
+use crate::physics_types::*;
+use crate::recorder::*;
+use crate::monitor::*;
+use crate::cluster::*;
+use crate::preset::*;
+use crate::snapshot::*;
 //use egui_macroquad;
 use macroquad::camera::Camera2D;
 use macroquad::prelude::*;
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::f32::consts::PI;
+use std::io;
+use std::path::Path;
+use std::time::Instant;
 
 pub struct Simulation {
     pub simulation_name: String,
@@ -19,8 +29,14 @@ pub struct Simulation {
     pub font: Font,
     pub world: Physics,
     pub camera: Camera2D,
+    camera_control: CameraControl,
+    fullscreen: bool,
+    /// Simulated time not yet consumed by fixed physics steps.
+    accumulator: f32,
+    step_once: bool,
     pub running: bool,
     pub sim_time: f64,
+    pub seed: u64,
     config: SimConfig,
     //pub ui: UISystem,
     pub sim_state: SimState,
@@ -30,6 +46,13 @@ pub struct Simulation {
     pub mouse_state: MouseState,
     //pub object_collector: ObjectCollector,
     pub elements: ElementCollector,
+    pub brush: Brush,
+    pub recorder: Recorder,
+    pub monitor: Monitor,
+    pub clusters: ClusterAnalysis,
+    /// Set after a preset was applied: restarts keep the current rules instead of drawing new ones,
+    /// until a new random sim is started.
+    preset_rules: Option<RuleSet>,
     info_time: Timer,
     info: bool,
     total_eng: f32,
@@ -41,7 +64,7 @@ pub struct Simulation {
 }
 
 impl Simulation {
-    pub fn new(configuration: SimConfig, font: Font) -> Self {
+    pub fn new(configuration: SimConfig, font: Font, seed: u64) -> Self {
         Self {
             simulation_name: String::new(),
 /*             world_size: Vec2 {
@@ -51,8 +74,13 @@ impl Simulation {
             font,
             world: Physics::new(),
             camera: create_camera(),
+            camera_control: CameraControl::new(),
+            fullscreen: false,
+            accumulator: 0.0,
+            step_once: false,
             running: true,
             sim_time: 0.0,
+            seed,
             config: configuration,
             //ui: UISystem::new(),
             sim_state: SimState::new(),
@@ -61,6 +89,11 @@ impl Simulation {
             select_phase: 0.0,
             mouse_state: MouseState { pos: Vec2::NAN },
             elements: ElementCollector::new(),
+            brush: Brush::default(),
+            recorder: Recorder::default(),
+            monitor: Monitor::default(),
+            clusters: ClusterAnalysis::default(),
+            preset_rules: None,
             info_time: Timer::new(1.0, true, true, false),
             info: true,
             total_eng: 0.0,
@@ -72,16 +105,20 @@ impl Simulation {
         }
     }
 
-    fn reset_sim(&mut self, sim_name: Option<&str>) {
-        let seed = generate_seed();
+    /// Restarts the world. The seed drives the only RNG stream used to build it (interaction matrix,
+    /// colors, element keys, types and positions), so the same seed and settings give the same world.
+    fn reset_sim(&mut self, sim_name: Option<&str>, seed: Option<u64>) {
+        let seed = seed.unwrap_or_else(generate_seed);
         println!("SEED: {}", seed);
         rand::srand(seed);
+        self.seed = seed;
         self.simulation_name = match sim_name {
             Some(name) => name.to_string(),
             None => String::new(),
         };
         self.world = Physics::new();
         self.elements = ElementCollector::new();
+        self.clusters.clear();
         //self.elements = ObjectCollector::new();
         self.sim_time = 0.0;
         self.sim_state = SimState::new();
@@ -97,12 +134,136 @@ impl Simulation {
     pub fn init(&mut self) {
         let settings = get_settings();
         self.elements.add_many_elements(settings.particles_num, &mut self.world);
+        if let Some(rules) = &self.preset_rules {
+            match PhysicsTypes::from_rule_set(rules) {
+                Ok(types) => {
+                    self.world.set_types(types);
+                    self.elements.apply_types(&mut self.world);
+                },
+                Err(e) => warn!("can't apply preset rules: {}", e),
+            }
+        }
+    }
+
+    /// Starts a new world from the preset. Machine specific settings (threads, neighbor search, display) are kept.
+    fn apply_preset(&mut self, preset: &Preset) {
+        if let Err(e) = PhysicsTypes::from_rule_set(&preset.rules) {
+            warn!("invalid preset {}: {}", preset.name, e);
+            return;
+        }
+        let current = get_settings();
+        let mut settings = preset.settings;
+        settings.particle_types = preset.rules.types_num;
+        settings.threads = current.threads;
+        settings.neighbor_search = current.neighbor_search;
+        settings.display = current.display;
+        settings.follow = current.follow;
+        settings.time_scale = current.time_scale;
+        settings.max_types = current.max_types;
+        set_settings(settings);
+        self.preset_rules = Some(preset.rules.clone());
+        self.reset_sim(Some(&preset.name), None);
+        info!("preset {} applied", preset.name);
+    }
+
+    fn save_preset(&self, name: &str) {
+        let preset = Preset::new(name, &format!("saved from seed {}", self.seed), get_settings(), self.world.types.to_rule_set());
+        let file_name: String = name.chars().map(|c| if c.is_alphanumeric() || c == '-' { c } else { '_' }).collect();
+        let path = Path::new(PRESETS_DIR).join(format!("{}.{}", file_name, PRESETS_EXT));
+        match preset.save(&path) {
+            Ok(_) => info!("preset saved to {}", path.display()),
+            Err(e) => warn!("can't save preset to {}: {}", path.display(), e),
+        }
     }
 
     fn update_particles(&mut self) {
-        for (id, elem) in self.elements.get_iter_mut() {
-            elem.update(&mut self.world);
+        self.elements.update_all(&mut self.world);
+    }
+
+    fn save_rules(&self) {
+        let path = timestamped_path(RULES_DIR, "rules", RULES_EXT);
+        match self.world.save_types(&path) {
+            Ok(_) => info!("rules saved to {}", path.display()),
+            Err(e) => warn!("can't save rules to {}: {}", path.display(), e),
+        }
+    }
+
+    fn load_rules(&mut self, path: &Path) {
+        match self.world.load_types(path) {
+            Ok(_) => {
+                info!("rules loaded from {}", path.display());
+                let mut settings = get_settings();
+                settings.particle_types = self.world.types.types_num();
+                set_settings(settings);
+                self.elements.apply_types(&mut self.world);
+            },
+            Err(e) => warn!("can't load rules from {}: {}", path.display(), e),
+        }
+    }
+
+    pub fn save_snapshot(&self, path: &Path) -> io::Result<()> {
+        let mut elements: Vec<ElementState> = self.elements.get_iter()
+            .map(|(_, element)| element.to_state(&self.world))
+            .collect();
+        elements.sort_by_key(|state| state.key);
+        let keys: HashMap<_, u64> = self.elements.get_iter().map(|(key, element)| (element.get_rigid_handle(), *key)).collect();
+        let bonds = self.world.bonds().iter()
+            .filter_map(|bond| Some(BondState { key1: keys.get(&bond.body1).copied()?, key2: keys.get(&bond.body2).copied()?, distance: bond.distance, kind: bond.kind() }))
+            .collect();
+        let snapshot = Snapshot {
+            version: SNAPSHOT_VERSION,
+            seed: self.seed,
+            sim_time: self.sim_state.sim_time,
+            settings: get_settings(),
+            rules: self.world.types.to_rule_set(),
+            elements,
+            bonds,
+        };
+        return snapshot.save(path);
+    }
+
+    /// Bodies are inserted again, so rapier handles, contacts and solver warm starting begin fresh.
+    /// The local thread count and type limit are kept, like when applying a preset.
+    pub fn load_snapshot(&mut self, path: &Path) -> io::Result<()> {
+        let snapshot = Snapshot::load(path)?;
+        let types = PhysicsTypes::from_rule_set(&snapshot.rules)?;
+        if snapshot.elements.iter().any(|state| state.physics_type >= types.types_num()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "snapshot element has unknown particle type"));
+        }
+        let keys: HashSet<u64> = snapshot.elements.iter().map(|state| state.key).collect();
+        if snapshot.bonds.iter().any(|bond| !keys.contains(&bond.key1) || !keys.contains(&bond.key2)) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "snapshot bond joins unknown elements"));
         }
+        let current = get_settings();
+        let mut settings = snapshot.settings;
+        settings.threads = current.threads;
+        settings.max_types = current.max_types;
+        set_settings(settings);
+        rand::srand(snapshot.seed);
+        self.seed = snapshot.seed;
+        self.world = Physics::new();
+        self.world.set_types(types);
+        self.elements = ElementCollector::new();
+        let mut handles = HashMap::new();
+        for state in snapshot.elements.iter() {
+            let element = Element::from_state(state, &mut self.world);
+            let (key, handle) = self.elements.insert(element);
+            handles.insert(key, handle);
+        }
+        for bond in snapshot.bonds.iter() {
+            self.world.restore_bond(handles[&bond.key1], handles[&bond.key2], bond.distance, bond.kind);
+        }
+        self.sim_state = SimState::new();
+        self.sim_state.sim_name = String::from(&self.simulation_name);
+        self.sim_state.sim_time = snapshot.sim_time;
+        self.sim_time = snapshot.sim_time;
+        self.accumulator = 0.0;
+        self.clusters.clear();
+        self.monitor.clear();
+        self.selected = 0;
+        self.select_phase = 0.0;
+        self.running = true;
+        Ok(())
     }
 
     fn set_particles_damping(&mut self, damping: f32) {
@@ -126,26 +287,86 @@ impl Simulation {
         self.update_sim_state();
         //self.check_agents_num();
         self.calc_selection_time();
+        let steps = self.steps_this_frame();
+        if steps == 0 {
+            return;
+        }
+        let step_start = Instant::now();
+        for _ in 0..steps {
+            self.step();
+        }
+        self.sim_state.step_time = step_start.elapsed().as_secs_f32() * 1000.0 / steps as f32;
+        self.record_sample();
+    }
+
+    /// Number of fixed physics steps due in this frame. The frame time scaled by the time scale goes into
+    /// an accumulator which is drained in steps of `dt`; at most `MAX_SUBSTEPS` run per frame and time
+    /// beyond that is dropped, so a slow frame can't snowball into ever longer ones.
+    fn steps_this_frame(&mut self) -> u32 {
+        let dt = self.world.get_dt();
+        if !self.running {
+            self.accumulator = 0.0;
+            return std::mem::take(&mut self.step_once) as u32;
+        }
+        self.accumulator += get_frame_time() * get_settings().time_scale;
+        let steps = ((self.accumulator / dt).floor() as u32).min(MAX_SUBSTEPS);
+        self.accumulator -= steps as f32 * dt;
+        if steps == MAX_SUBSTEPS {
+            self.accumulator = self.accumulator.min(dt);
+        }
+        return steps;
+    }
+
+    /// One force update and one physics step.
+    fn step(&mut self) {
         self.update_particles();
         self.world.step_physics();
+        self.sim_state.sim_time += self.world.get_dt() as f64;
+        self.clusters.update(&self.elements, self.world.types.types_num(), self.sim_state.sim_time);
+    }
+
+    fn record_sample(&mut self) {
+        if self.monitor.paused {
+            return;
+        }
+        self.monitor.push(Sample {
+            sim_time: self.sim_state.sim_time,
+            total_k_eng: self.sim_state.total_k_eng,
+            fps: self.sim_state.fps,
+            step_time: self.sim_state.step_time,
+            type_speeds: self.elements.type_speeds(&self.world),
+            bonds: self.world.bond_stats(),
+        });
     }
 
     pub fn draw(&mut self) {
         let settings = get_settings();
         //let w = settings.width;
         //let h = settings.height;
-        let r = settings.world_radius/2.0;
         //set_default_camera();
         set_camera(&self.camera);
         clear_background(BLACK);
         self.world.debug_draw();
         //draw_rectangle_lines(0.0, 0.0, w, h, 3.0, WHITE);
         //draw_circle_lines(0.0, 0.0, r, 2.0, MAGENTA);
-        draw_smooth_circle(r, Vec2::ZERO, 32., 1.0, LIGHTGRAY);
+        settings.boundary.draw(settings.world_radius);
+        // a paused world shows the real body positions, e.g. the result of a single step
+        let alpha = match self.running {
+            true => (self.accumulator / self.world.get_dt()).clamp(0.0, 1.0),
+            false => 1.0,
+        };
+        self.elements.interpolate(&self.world, alpha);
         //self.draw_grid(50);
         self.draw_particles();
+        if !self.ui.is_pointer_over() {
+            let (mouse_x, mouse_y) = mouse_position();
+            self.brush.draw(self.camera.screen_to_world(Vec2::new(mouse_x, mouse_y)));
+        }
+        // recordings show the world only, without menus and windows
+        self.recorder.capture_frame();
         self.draw_info();
         self.draw_ui();
+        self.recorder.draw_indicator(&self.font);
     }
 
     fn draw_info(&mut self) {
@@ -160,8 +381,17 @@ impl Simulation {
 
     fn draw_particles(&self) {
         let settings = get_settings();
+        let by_cluster = self.clusters.enabled && self.clusters.color_by_cluster;
+        self.elements.draw_bonds(&self.world);
         for (id, p) in self.elements.get_iter() {
-            p.draw(settings.display, &self.world);
+            if by_cluster {
+                p.draw_colored(self.clusters.color_of(*id));
+            } else {
+                p.draw(settings.display, &self.world);
+            }
+        }
+        if let Some(selected) = self.elements.get(self.selected) {
+            selected.draw_selection(self.select_phase);
         }
     }
 
@@ -170,13 +400,57 @@ impl Simulation {
         if signals.shuffle_interactions {
             signals.shuffle_interactions = false;
             self.world.random_types();
+            self.elements.apply_types(&mut self.world);
+        }
+        if signals.types_num_changed {
+            signals.types_num_changed = false;
+            self.world.random_types();
+            self.elements.randomize_types(&mut self.world);
+        }
+        if signals.save_rules {
+            signals.save_rules = false;
+            self.save_rules();
+        }
+        if signals.save_snapshot {
+            signals.save_snapshot = false;
+            let path = timestamped_path(SNAPSHOTS_DIR, "snapshot", SNAPSHOTS_EXT);
+            match self.save_snapshot(&path) {
+                Ok(_) => info!("snapshot saved to {}", path.display()),
+                Err(e) => warn!("can't save snapshot to {}: {}", path.display(), e),
+            }
+        }
+        if signals.toggle_pause {
+            signals.toggle_pause = false;
+            self.running = !self.running;
+        }
+        if signals.single_step {
+            signals.single_step = false;
+            self.running = false;
+            self.step_once = true;
+        }
+        if signals.toggle_fullscreen {
+            signals.toggle_fullscreen = false;
+            self.fullscreen = !self.fullscreen;
+            set_fullscreen(self.fullscreen);
+        }
+        if signals.export_series {
+            signals.export_series = false;
+            let path = timestamped_path(SERIES_DIR, "series", SERIES_EXT);
+            match self.monitor.export(&path) {
+                Ok(_) => info!("series exported to {}", path.display()),
+                Err(e) => warn!("can't export series to {}: {}", path.display(), e),
+            }
         }
         if signals.start_new_sim {
             signals.start_new_sim = false;
-            self.reset_sim(None);
+            self.preset_rules = None;
+            self.reset_sim(None, None);
         }
         if signals.restart {
-            self.reset_sim(None);
+            if self.preset_rules.is_some() {
+                self.preset_rules = Some(self.world.types.to_rule_set());
+            }
+            self.reset_sim(None, Some(self.seed));
             signals.restart = false;
         }
         if self.signals.quit {
@@ -193,18 +467,93 @@ impl Simulation {
 
     pub fn input(&mut self) {
         self.mouse_input();
-        control_camera(&mut self.camera);
+        let mut signals = signals();
+        if is_key_pressed(KeyCode::F11) {
+            signals.toggle_fullscreen = true;
+        }
+        if !self.ui.wants_keyboard() {
+            if is_key_pressed(KeyCode::Space) {
+                signals.toggle_pause = true;
+            }
+            if is_key_pressed(KeyCode::Period) {
+                signals.single_step = true;
+            }
+        }
+        set_signals(signals);
+        let follow = self.follow_target();
+        self.camera_control.update(&mut self.camera, self.ui.is_pointer_over(), self.ui.wants_keyboard(), follow);
+    }
+
+    fn follow_target(&self) -> Option<Vec2> {
+        let element = self.elements.get(self.selected)?;
+        return match get_settings().follow {
+            Follow::Off => None,
+            Follow::Particle => Some(element.pos),
+            Follow::Cluster => {
+                let id = self.clusters.cluster_of(element.key)?;
+                self.clusters.report.clusters.get(id).map(|cluster| cluster.center)
+            },
+        };
     }
 
     fn mouse_input(&mut self) {
-        if is_mouse_button_released(MouseButton::Left) {
-            //if !self.ui.pointer_over {
-            if true {
-                self.selected = 0;
-                let (mouse_posx, mouse_posy) = mouse_position();
-                let mouse_pos = Vec2::new(mouse_posx, mouse_posy);
-                let rel_coords = self.camera.screen_to_world(mouse_pos);
-            }
+        if self.ui.is_pointer_over() {
+            return;
+        }
+        let (mouse_posx, mouse_posy) = mouse_position();
+        let mouse_pos = Vec2::new(mouse_posx, mouse_posy);
+        let rel_coords = self.camera.screen_to_world(mouse_pos);
+        match self.brush.tool {
+            BrushTool::Select => {
+                if is_mouse_button_released(MouseButton::Left) {
+                    self.selected = 0;
+                    let pick_radius = screen_to_world_distance(&self.camera, 12.0);
+                    if let Some(key) = self.elements.find_nearest(rel_coords, pick_radius) {
+                        self.selected = key;
+                    }
+                }
+            },
+            _ => {
+                if is_mouse_button_down(MouseButton::Left) {
+                    self.apply_brush(rel_coords);
+                }
+            },
+        }
+    }
+
+    fn apply_brush(&mut self, center: Vec2) {
+        let radius = self.brush.radius;
+        let strength = self.brush.strength;
+        match self.brush.tool {
+            BrushTool::Select => {},
+            BrushTool::Spawn => {
+                let types_num = self.world.types.types_num();
+                let p_type = self.brush.spawn_type.min(types_num - 1) as u128;
+                for _ in 0..self.brush.spawn_count() {
+                    let pos = center + random_circle_position(radius);
+                    self.elements.spawn(pos, p_type, &mut self.world);
+                }
+            },
+            BrushTool::Erase => {
+                for key in self.elements.keys_in_range(center, radius) {
+                    if let Some(element) = self.elements.get(key) {
+                        self.world.remove_physics_object(element.get_rigid_handle());
+                    }
+                    self.elements.remove(key);
+                }
+            },
+            BrushTool::Attract | BrushTool::Repel | BrushTool::Kick => {
+                for key in self.elements.keys_in_range(center, radius) {
+                    let element = self.elements.get(key).unwrap();
+                    let falloff = 1.0 - element.pos.distance(center) / radius;
+                    let impulse = match self.brush.tool {
+                        BrushTool::Attract => (center - element.pos).normalize_or_zero() * strength * falloff,
+                        BrushTool::Repel => (element.pos - center).normalize_or_zero() * strength * falloff,
+                        _ => random_unit_vec2() * strength,
+                    };
+                    self.world.apply_impulse(element.get_rigid_handle(), impulse);
+                }
+            },
         }
     }
 
@@ -212,7 +561,6 @@ impl Simulation {
         self.sim_state.fps = get_fps();
         self.sim_state.dt = get_frame_time();
         self.sim_state.total_k_eng = self.world.get_total_kinetic_eng().round();
-        self.sim_state.sim_time += self.sim_state.dt as f64;
         let (mouse_x, mouse_y) = mouse_position();
         self.mouse_state.pos = Vec2::new(mouse_x, mouse_y);
         self.sim_state.physics_num = self.world.get_physics_obj_num() as i32;
@@ -230,7 +578,47 @@ impl Simulation {
     }
 
     pub fn process_ui(&mut self) {
-        self.ui.process(self.fps, self.avg);
+        let selected = self.elements.get(self.selected).map(|element| element.info(&self.world));
+        self.ui.process(UIFrame {
+            fps: self.fps,
+            fps2: self.avg,
+            seed: self.seed,
+            running: self.running,
+            selected: selected.as_ref(),
+            types: &mut self.world.types,
+            brush: &mut self.brush,
+            recorder: &mut self.recorder,
+            monitor: &mut self.monitor,
+            clusters: &mut self.clusters,
+        });
+        if self.ui.take_deselect() {
+            self.selected = 0;
+        }
+        if let Some(seed) = self.ui.take_seed_to_start() {
+            self.preset_rules = None;
+            self.reset_sim(None, Some(seed));
+        }
+        if let Some(path) = self.ui.take_rules_file() {
+            self.load_rules(&path);
+        }
+        if let Some(preset) = self.ui.take_preset() {
+            self.apply_preset(&preset);
+        }
+        if let Some(path) = self.ui.take_preset_file() {
+            match Preset::load(&path) {
+                Ok(preset) => self.apply_preset(&preset),
+                Err(e) => warn!("can't load preset from {}: {}", path.display(), e),
+            }
+        }
+        if let Some(name) = self.ui.take_preset_name() {
+            self.save_preset(&name);
+        }
+        if let Some(path) = self.ui.take_snapshot_file() {
+            match self.load_snapshot(&path) {
+                Ok(_) => info!("snapshot loaded from {}", path.display()),
+                Err(e) => warn!("can't load snapshot from {}: {}", path.display(), e),
+            }
+        }
     }
 
     pub fn draw_ui(&self) {
@@ -242,6 +630,9 @@ impl Simulation {
     }
 }
 
+/// Most physics steps run in one frame, more would make the UI unresponsive.
+pub const MAX_SUBSTEPS: u32 = 8;
+
 //?         [[[SIM_CONFIG]]]
 #[derive(Clone, Copy)]
 pub struct SimConfig {
@@ -303,6 +694,8 @@ pub struct SimState {
     pub sim_time: f64,
     pub fps: i32,
     pub dt: f32,
+    /// Wall time of the last force and physics update, in milliseconds.
+    pub step_time: f32,
     pub total_k_eng: f32,
 }
 
@@ -319,6 +712,7 @@ impl SimState {
             sim_time: 0.0,
             fps: 0,
             dt: 0.0,
+            step_time: 0.0,
             total_k_eng: 0.0,
         }
     }
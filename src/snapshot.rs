@@ -0,0 +1,150 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::bonding::BondKind;
+use crate::globals::*;
+use crate::physics_types::*;
+
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+//?         [[[ELEMENT_STATE]]]
+/// Everything needed to put a single particle back into the physics world.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ElementState {
+    pub key: u64,
+    pub physics_type: usize,
+    pub pos: [f32; 2],
+    pub rot: f32,
+    pub lin_vel: [f32; 2],
+    pub ang_vel: f32,
+    pub size: f32,
+    /// Collider density, missing in snapshots saved before it was stored.
+    #[serde(default)]
+    pub density: Option<f32>,
+}
+
+//?         [[[BOND_STATE]]]
+/// Bond between the particles with element keys `key1` and `key2`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BondState {
+    pub key1: u64,
+    pub key2: u64,
+    pub distance: f32,
+    pub kind: BondKind,
+}
+
+//?         [[[SNAPSHOT]]]
+/// Frozen state of a whole running world.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub seed: u64,
+    pub sim_time: f64,
+    pub settings: Settings,
+    pub rules: RuleSet,
+    pub elements: Vec<ElementState>,
+    /// Missing in snapshots saved before bonds were stored.
+    #[serde(default)]
+    pub bonds: Vec<BondState>,
+}
+
+impl Snapshot {
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string(self)?;
+        fs::write(path, json)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let json = fs::read_to_string(path)?;
+        let snapshot: Snapshot = serde_json::from_str(&json)?;
+        if snapshot.version > SNAPSHOT_VERSION {
+            let msg = format!("unsupported snapshot version {} (max {})", snapshot.version, SNAPSHOT_VERSION);
+            return Err(io::Error::new(ErrorKind::InvalidData, msg));
+        }
+        Ok(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        let rules = RuleSet {
+            version: RULES_VERSION,
+            types_num: 2,
+            actions: vec![vec![0.5, -0.25], vec![0.125, 1.0]],
+            fields: vec![1.0, 1.5],
+            colors: vec![[1.0, 0.0, 0.0, 1.0], [0.0, 0.5, 1.0, 1.0]],
+            collisions: vec![],
+            bonds: vec![],
+            reactions: vec![],
+        };
+        let elements = (0..3).map(|i| ElementState {
+            key: 10 + i,
+            physics_type: i as usize % 2,
+            pos: [i as f32 * 3.5, -1.25],
+            rot: 0.75,
+            lin_vel: [0.5, i as f32],
+            ang_vel: -0.1,
+            size: 2.0,
+            density: Some(1.5),
+        }).collect();
+        let bonds = vec![
+            BondState { key1: 10, key2: 11, distance: 4.0, kind: BondKind::Rope },
+            BondState { key1: 11, key2: 12, distance: 5.5, kind: BondKind::Spring },
+        ];
+        return Snapshot { version: SNAPSHOT_VERSION, seed: 77, sim_time: 12.5, settings: Settings::default(), rules, elements, bonds };
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        return std::env::temp_dir().join(format!("madlab_snapshot_{}_{}", std::process::id(), name)).join("snapshot.json");
+    }
+
+    #[test]
+    fn snapshot_round_trips_through_a_file() {
+        let saved = snapshot();
+        let path = temp_path("round_trip");
+        saved.save(&path).unwrap();
+        let loaded = Snapshot::load(&path).unwrap();
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert_eq!(serde_json::to_string(&loaded).unwrap(), serde_json::to_string(&saved).unwrap());
+        assert_eq!(loaded.seed, 77);
+        assert_eq!(loaded.elements[2].pos, [7.0, -1.25]);
+        assert_eq!(loaded.elements[1].density, Some(1.5));
+        assert_eq!(loaded.bonds.len(), 2);
+        assert!(loaded.bonds[1].kind == BondKind::Spring && loaded.bonds[1].key1 == 11 && loaded.bonds[1].distance == 5.5);
+    }
+
+    #[test]
+    fn snapshot_without_bonds_loads() {
+        let mut json: serde_json::Value = serde_json::to_value(snapshot()).unwrap();
+        json.as_object_mut().unwrap().remove("bonds");
+        let loaded: Snapshot = serde_json::from_value(json).unwrap();
+        assert!(loaded.bonds.is_empty());
+        assert_eq!(loaded.elements.len(), 3);
+    }
+
+    #[test]
+    fn newer_snapshot_versions_are_rejected() {
+        let mut saved = snapshot();
+        saved.version = SNAPSHOT_VERSION + 1;
+        let path = temp_path("version");
+        saved.save(&path).unwrap();
+        let loaded = Snapshot::load(&path);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert_eq!(loaded.err().map(|e| e.kind()), Some(ErrorKind::InvalidData));
+    }
+
+    #[test]
+    fn element_without_density_loads() {
+        let json = r#"{"key":1,"physics_type":0,"pos":[1.0,2.0],"rot":0.0,"lin_vel":[0.0,0.0],"ang_vel":0.0,"size":1.0}"#;
+        let state: ElementState = serde_json::from_str(json).unwrap();
+        assert_eq!(state.density, None);
+    }
+}
@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+
+//?         [[[SOLVER_SETTINGS]]]
+/// Rapier solver knobs trading accuracy for speed. Applied to the live world before every physics step.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SolverSettings {
+    pub solver_iterations: usize,
+    pub friction_iterations: usize,
+    pub min_island_size: usize,
+    pub prediction_distance: f32,
+    pub allowed_linear_error: f32,
+    pub max_ccd_substeps: usize,
+    pub gravity: [f32; 2],
+}
+
+impl Default for SolverSettings {
+    fn default() -> Self {
+        Self {
+            solver_iterations: 1,
+            friction_iterations: 2,
+            min_island_size: 32,
+            prediction_distance: 0.005,
+            allowed_linear_error: 0.004,
+            max_ccd_substeps: 1,
+            gravity: [0.0, 0.0],
+        }
+    }
+}
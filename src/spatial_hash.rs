@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+use macroquad::prelude::*;
+use rapier2d::prelude::RigidBodyHandle;
+use serde::{Deserialize, Serialize};
+use crate::util::*;
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+pub enum NeighborSearch {
+    /// Uniform grid rebuilt once per physics step.
+    #[default]
+    Grid,
+    /// Per particle `Ball` shape query against the rapier query pipeline.
+    Rapier,
+}
+
+#[derive(Clone, Copy)]
+pub struct NeighborEntry {
+    pub handle: RigidBodyHandle,
+    pub pos: Vec2,
+    pub p_type: u128,
+}
+
+//?         [[[SPATIAL_HASH]]]
+/// Cell list over particle centers. With the cell size equal to the largest query radius
+/// every range query only has to look into the 3x3 block of cells around the particle.
+/// With a `period` set the world is treated as a torus and queries see across the seam.
+pub struct SpatialHash {
+    cell_size: f32,
+    period: Option<f32>,
+    cells_per_side: i32,
+    cells: HashMap<(i32, i32), Vec<usize>>,
+    entries: Vec<NeighborEntry>,
+}
+
+impl SpatialHash {
+
+    pub fn new() -> Self {
+        Self {
+            cell_size: 1.0,
+            period: None,
+            cells_per_side: 0,
+            cells: HashMap::new(),
+            entries: vec![],
+        }
+    }
+
+    pub fn rebuild(&mut self, cell_size: f32, period: Option<f32>, entries: impl Iterator<Item = NeighborEntry>) {
+        self.cell_size = cell_size.max(1.0);
+        self.period = period;
+        if let Some(size) = period {
+            // cells have to tile the periodic square exactly
+            self.cells_per_side = ((size / self.cell_size).floor() as i32).max(1);
+            self.cell_size = size / self.cells_per_side as f32;
+        }
+        self.cells.clear();
+        self.entries.clear();
+        self.entries.extend(entries);
+        for (i, entry) in self.entries.iter().enumerate() {
+            let cell = self.wrap_cell(self.cell_of(entry.pos));
+            self.cells.entry(cell).or_default().push(i);
+        }
+    }
+
+    fn cell_of(&self, pos: Vec2) -> (i32, i32) {
+        let offset = self.period.unwrap_or(0.0) / 2.0;
+        return (((pos.x + offset) / self.cell_size).floor() as i32, ((pos.y + offset) / self.cell_size).floor() as i32);
+    }
+
+    fn wrap_cell(&self, (cx, cy): (i32, i32)) -> (i32, i32) {
+        return match self.period {
+            Some(_) => (cx.rem_euclid(self.cells_per_side), cy.rem_euclid(self.cells_per_side)),
+            None => (cx, cy),
+        };
+    }
+
+    /// Calls `f` for every entry closer than `radius` to `pos` (including the particle at `pos` itself).
+    /// In a periodic world the entry is passed with the position of its nearest image.
+    pub fn for_each_in_range<F: FnMut(&NeighborEntry)>(&self, pos: Vec2, radius: f32, mut f: F) {
+        let (mut x0, mut y0) = self.cell_of(pos - Vec2::splat(radius));
+        let (mut x1, mut y1) = self.cell_of(pos + Vec2::splat(radius));
+        if self.period.is_some() && x1 - x0 >= self.cells_per_side {
+            (x0, x1) = (0, self.cells_per_side - 1);
+        }
+        if self.period.is_some() && y1 - y0 >= self.cells_per_side {
+            (y0, y1) = (0, self.cells_per_side - 1);
+        }
+        let r2 = radius * radius;
+        for cx in x0..=x1 {
+            for cy in y0..=y1 {
+                if let Some(cell) = self.cells.get(&self.wrap_cell((cx, cy))) {
+                    for &i in cell.iter() {
+                        let entry = &self.entries[i];
+                        match self.period {
+                            Some(size) => {
+                                let image = pos + periodic_delta(pos, entry.pos, size);
+                                if image.distance_squared(pos) <= r2 {
+                                    f(&NeighborEntry { pos: image, ..*entry });
+                                }
+                            },
+                            None => {
+                                if entry.pos.distance_squared(pos) <= r2 {
+                                    f(entry);
+                                }
+                            },
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn entries(&self) -> &[NeighborEntry] {
+        return &self.entries;
+    }
+
+    pub fn len(&self) -> usize {
+        return self.entries.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(i: u32, x: f32, y: f32) -> NeighborEntry {
+        return NeighborEntry { handle: RigidBodyHandle::from_raw_parts(i, 0), pos: Vec2::new(x, y), p_type: 0 };
+    }
+
+    /// Deterministic points spread over the square `[-size/2, size/2)`.
+    fn scattered(n: u32, size: f32) -> Vec<NeighborEntry> {
+        let mut state: u64 = 0x9E3779B97F4A7C15;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            return (state % 10_000) as f32 / 10_000.0;
+        };
+        return (0..n).map(|i| entry(i, (next() - 0.5) * size, (next() - 0.5) * size)).collect();
+    }
+
+    fn found(hash: &SpatialHash, pos: Vec2, radius: f32) -> Vec<(u32, Vec2)> {
+        let mut found = vec![];
+        hash.for_each_in_range(pos, radius, |neighbor| found.push((neighbor.handle.into_raw_parts().0, neighbor.pos)));
+        found.sort_by_key(|(i, _)| *i);
+        return found;
+    }
+
+    #[test]
+    fn open_query_matches_brute_force() {
+        let entries = scattered(500, 1000.0);
+        let mut hash = SpatialHash::new();
+        hash.rebuild(60.0, None, entries.iter().copied());
+        for radius in [10.0, 60.0, 150.0] {
+            for particle in entries.iter().take(50) {
+                let expected: Vec<(u32, Vec2)> = entries.iter()
+                    .filter(|other| other.pos.distance(particle.pos) <= radius)
+                    .map(|other| (other.handle.into_raw_parts().0, other.pos))
+                    .collect();
+                assert_eq!(found(&hash, particle.pos, radius), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn periodic_query_sees_across_the_seam() {
+        let mut hash = SpatialHash::new();
+        hash.rebuild(10.0, Some(100.0), [entry(0, 49.0, 0.0), entry(1, -49.0, 0.0), entry(2, 0.0, -49.5), entry(3, 0.0, 20.0)].into_iter());
+        assert_eq!(found(&hash, Vec2::new(49.0, 0.0), 5.0), vec![(0, Vec2::new(49.0, 0.0)), (1, Vec2::new(51.0, 0.0))]);
+        // across both seams at once, entry 2 is seen at its image above the top edge
+        assert_eq!(found(&hash, Vec2::new(0.0, 49.0), 2.0), vec![(2, Vec2::new(0.0, 50.5))]);
+        assert!(found(&hash, Vec2::new(0.0, 0.0), 5.0).is_empty());
+    }
+
+    #[test]
+    fn periodic_query_matches_brute_force() {
+        let size = 300.0;
+        let entries = scattered(400, size);
+        let mut hash = SpatialHash::new();
+        hash.rebuild(45.0, Some(size), entries.iter().copied());
+        for radius in [20.0, 45.0, 100.0] {
+            for particle in entries.iter().take(50) {
+                let expected: Vec<u32> = entries.iter()
+                    .filter(|other| periodic_delta(particle.pos, other.pos, size).length() <= radius)
+                    .map(|other| other.handle.into_raw_parts().0)
+                    .collect();
+                let ids: Vec<u32> = found(&hash, particle.pos, radius).iter().map(|(i, _)| *i).collect();
+                assert_eq!(ids, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn periodic_query_larger_than_the_world_reports_every_entry_once() {
+        let size = 100.0;
+        let entries = scattered(50, size);
+        let mut hash = SpatialHash::new();
+        hash.rebuild(30.0, Some(size), entries.iter().copied());
+        let ids: Vec<u32> = found(&hash, Vec2::ZERO, 500.0).iter().map(|(i, _)| *i).collect();
+        assert_eq!(ids, (0..50).collect::<Vec<u32>>());
+    }
+}
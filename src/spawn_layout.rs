@@ -0,0 +1,58 @@
+use std::f32::consts::PI;
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::util::*;
+
+//?         [[[SPAWN_LAYOUT]]]
+/// Where new particles are put when a world is started. `size` passed to the methods is
+/// `Settings::world_radius`, the same as for `Boundary`.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+pub enum SpawnLayout {
+    /// Random points over the whole world, denser toward the center.
+    #[default]
+    Disk,
+    /// Small dense disk in the middle of the world.
+    Center,
+    /// Thin ring around the center.
+    Ring,
+    /// `count` separate blobs placed evenly around the center.
+    Blobs { count: usize },
+}
+
+impl SpawnLayout {
+
+    pub fn all() -> [SpawnLayout; 4] {
+        return [SpawnLayout::Disk, SpawnLayout::Center, SpawnLayout::Ring, SpawnLayout::Blobs { count: 6 }];
+    }
+
+    pub fn name(&self) -> &'static str {
+        return match self {
+            SpawnLayout::Disk => "Disk",
+            SpawnLayout::Center => "Center",
+            SpawnLayout::Ring => "Ring",
+            SpawnLayout::Blobs { .. } => "Blobs",
+        };
+    }
+
+    pub fn same_kind(&self, other: &SpawnLayout) -> bool {
+        return std::mem::discriminant(self) == std::mem::discriminant(other);
+    }
+
+    /// Position of the `i`-th spawned particle.
+    pub fn position(&self, i: usize, size: f32) -> Vec2 {
+        let half = size / 2.0;
+        return match *self {
+            SpawnLayout::Disk => random_circle_position(half),
+            SpawnLayout::Center => random_circle_position(half / 6.0),
+            SpawnLayout::Ring => {
+                let r = half * rand::gen_range(0.55, 0.6);
+                Vec2::from_angle(rand::gen_range(0.0, 2.0 * PI)) * r
+            },
+            SpawnLayout::Blobs { count } => {
+                let count = count.max(1);
+                let center = Vec2::from_angle(2.0 * PI * (i % count) as f32 / count as f32) * half * 0.5;
+                center + random_circle_position(half / 8.0)
+            },
+        };
+    }
+}
@@ -1,19 +1,72 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use egui_macroquad::{*, egui::{menu, Align2, Color32, ColorImage, Context, Label, RichText, Slider, TextureHandle, TopBottomPanel, Ui, Window}};
+use egui_macroquad::{*, egui::{menu, Align2, Color32, ColorImage, ComboBox, Context, DragValue, Grid, Label, RichText, ScrollArea, Slider, TextEdit, TextureHandle, TopBottomPanel, Ui, Window}};
 use egui_macroquad::egui::Vec2 as UIVec2;
+use egui_macroquad::egui::plot::{Legend, Line, Plot, PlotPoints};
 use macroquad::time::{get_frame_time, get_fps};
 use macroquad::math::clamp; 
 use egui_macroquad::egui::vec2;
+use crate::bonding::*;
+use crate::boundary::*;
+use crate::camera::Follow;
+use crate::brush::*;
+use crate::cluster::*;
+use crate::element::ElementInfo;
+use crate::force_law::*;
 use crate::globals::*;
+use crate::physics_types::*;
+use crate::preset::*;
+use crate::reaction::*;
+use crate::monitor::*;
+use crate::recorder::*;
+use crate::solver::SolverSettings;
+use crate::spatial_hash::NeighborSearch;
+use crate::spawn_layout::*;
+use crate::sim::MAX_SUBSTEPS;
+use crate::util::*;
+
+//?         [[[UI_FRAME]]]
+/// Everything the UI shows and edits in one frame.
+pub struct UIFrame<'a> {
+    pub fps: i32,
+    pub fps2: i32,
+    pub seed: u64,
+    pub running: bool,
+    pub selected: Option<&'a ElementInfo>,
+    pub types: &'a mut PhysicsTypes,
+    pub brush: &'a mut Brush,
+    pub recorder: &'a mut Recorder,
+    pub monitor: &'a mut Monitor,
+    pub clusters: &'a mut ClusterAnalysis,
+}
 
 pub struct UI {
     pointer_over: bool,
+    keyboard_over: bool,
     monitor_win: bool,
     settings_win: bool,
+    solver_win: bool,
     about_win: bool,
+    seed_win: bool,
+    matrix_win: bool,
+    collision_win: bool,
+    bonds_win: bool,
+    reactions_win: bool,
+    tools_win: bool,
+    capture_win: bool,
+    clusters_win: bool,
+    deselect: bool,
+    seed_text: String,
+    seed_to_start: Option<u64>,
     logo: Option<egui_macroquad::egui::TextureHandle>,
     big_logo: Option<egui_macroquad::egui::TextureHandle>,
+    rules_file: Option<PathBuf>,
+    snapshot_file: Option<PathBuf>,
+    presets: Vec<Preset>,
+    preset: Option<Preset>,
+    preset_file: Option<PathBuf>,
+    preset_name: String,
+    preset_to_save: Option<String>,
 }
 
 
@@ -23,11 +76,31 @@ impl UI {
         //let img =  Self::load_image(Path::new("assets/img/atom.png")).unwrap();
         Self {
             pointer_over: false,
+            keyboard_over: false,
             monitor_win: false,
             settings_win: false,
+            solver_win: false,
             about_win: false,
+            seed_win: false,
+            matrix_win: false,
+            collision_win: false,
+            bonds_win: false,
+            reactions_win: false,
+            tools_win: false,
+            capture_win: false,
+            clusters_win: false,
+            deselect: false,
+            seed_text: String::new(),
+            seed_to_start: None,
             logo: Self::load_textures("science32"),
             big_logo: Self::load_textures("science128"),
+            rules_file: None,
+            snapshot_file: None,
+            presets: Preset::builtin(),
+            preset: None,
+            preset_file: None,
+            preset_name: String::new(),
+            preset_to_save: None,
         }
     }
 
@@ -49,12 +122,24 @@ impl UI {
         return texture;
     }
 
-    pub fn process(&mut self, fps: i32, fps2: i32) {
+    pub fn process(&mut self, frame: UIFrame) {
+        let UIFrame { fps, fps2, seed, running, selected, types, brush, recorder, monitor, clusters } = frame;
         egui_macroquad::ui(|egui_ctx| {
             self.pointer_over = egui_ctx.is_pointer_over_area();
-            self.build_top_menu(egui_ctx);
-            self.build_monitor_win(egui_ctx, fps, fps2);
+            self.keyboard_over = egui_ctx.wants_keyboard_input();
+            self.build_top_menu(egui_ctx, seed, recorder, running);
+            self.build_monitor_win(egui_ctx, fps, fps2, seed, types, monitor);
             self.build_settings_win(egui_ctx);
+            self.build_solver_win(egui_ctx);
+            self.build_seed_win(egui_ctx, seed);
+            self.build_matrix_win(egui_ctx, types);
+            self.build_collision_win(egui_ctx, types);
+            self.build_bonds_win(egui_ctx, types);
+            self.build_reactions_win(egui_ctx, types);
+            self.build_inspector_win(egui_ctx, types, selected);
+            self.build_tools_win(egui_ctx, types, brush);
+            self.build_capture_win(egui_ctx, recorder);
+            self.build_clusters_win(egui_ctx, types, clusters);
             self.build_about_win(egui_ctx);
         });
     }
@@ -63,7 +148,59 @@ impl UI {
         egui_macroquad::draw();
     }
 
-    fn build_top_menu(&mut self, egui_ctx: &Context) {
+    pub fn take_rules_file(&mut self) -> Option<PathBuf> {
+        return self.rules_file.take();
+    }
+
+    pub fn is_pointer_over(&self) -> bool {
+        return self.pointer_over;
+    }
+
+    /// True while a text field has focus, so key shortcuts must be ignored.
+    pub fn wants_keyboard(&self) -> bool {
+        return self.keyboard_over;
+    }
+
+    pub fn take_deselect(&mut self) -> bool {
+        return std::mem::take(&mut self.deselect);
+    }
+
+    pub fn take_seed_to_start(&mut self) -> Option<u64> {
+        return self.seed_to_start.take();
+    }
+
+    pub fn take_snapshot_file(&mut self) -> Option<PathBuf> {
+        return self.snapshot_file.take();
+    }
+
+    pub fn take_preset(&mut self) -> Option<Preset> {
+        return self.preset.take();
+    }
+
+    pub fn take_preset_file(&mut self) -> Option<PathBuf> {
+        return self.preset_file.take();
+    }
+
+    pub fn take_preset_name(&mut self) -> Option<String> {
+        return self.preset_to_save.take();
+    }
+
+    fn files_menu(ui: &mut Ui, dir: &str, ext: &str) -> Option<PathBuf> {
+        let files = list_files(dir, ext);
+        if files.is_empty() {
+            ui.label(RichText::new("nothing saved yet").italics());
+        }
+        for path in files {
+            let name = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+            if ui.button(name).clicked() {
+                ui.close_menu();
+                return Some(path);
+            }
+        }
+        return None;
+    }
+
+    fn build_top_menu(&mut self, egui_ctx: &Context, seed: u64, recorder: &mut Recorder, running: bool) {
         let mut signals = signals();
         let mut settings =  get_settings();
         TopBottomPanel::top("top_panel").default_height(100.0).show(egui_ctx, |ui| {
@@ -83,16 +220,50 @@ impl UI {
                         signals.start_new_sim = true;
                         set_signals(signals);
                     }
+                    if ui.button(RichText::new("Start With Seed...").strong().color(Color32::GREEN)).clicked() {
+                        self.seed_win = !self.seed_win;
+                        self.seed_text = seed.to_string();
+                    }
+                    if ui.button(RichText::new("Restart (Same Seed)").strong().color(Color32::GREEN)).clicked() {
+                        signals.restart = true;
+                        set_signals(signals);
+                    }
+                    if ui.button(RichText::new("Save Rules").strong().color(Color32::LIGHT_BLUE)).clicked() {
+                        signals.save_rules = true;
+                        set_signals(signals);
+                    }
+                    ui.menu_button(RichText::new("Load Rules").strong().color(Color32::LIGHT_BLUE), |ui| {
+                        if let Some(path) = Self::files_menu(ui, RULES_DIR, RULES_EXT) {
+                            self.rules_file = Some(path);
+                        }
+                    });
+                    if ui.button(RichText::new("Save Snapshot").strong().color(Color32::GOLD)).clicked() {
+                        signals.save_snapshot = true;
+                        set_signals(signals);
+                    }
+                    ui.menu_button(RichText::new("Load Snapshot").strong().color(Color32::GOLD), |ui| {
+                        if let Some(path) = Self::files_menu(ui, SNAPSHOTS_DIR, SNAPSHOTS_EXT) {
+                            self.snapshot_file = Some(path);
+                        }
+                    });
                     if ui.button(RichText::new("Quit").strong().color(Color32::RED)).clicked() {
                         std::process::exit(0);
                     }
                 });
 
+                ui.separator();
+                menu::menu_button(ui, RichText::new("PRESETS").strong(), |ui| {
+                    self.build_presets_menu(ui);
+                });
+
                 ui.separator();
                 menu::menu_button(ui, RichText::new("SETTINGS").strong(), |ui| {
                     if ui.button(RichText::new("Settings").strong().color(Color32::GREEN)).clicked() {
                         self.settings_win = !self.settings_win;
                     }
+                    if ui.button(RichText::new("Advanced Physics").strong().color(Color32::GOLD)).clicked() {
+                        self.solver_win = !self.solver_win;
+                    }
                 });
 
                 ui.separator();
@@ -118,10 +289,45 @@ impl UI {
                         settings.collisions = !settings.collisions;
                         set_settings(settings);
                     }
+                    ui.menu_button(RichText::new(format!("Boundary: {}", settings.boundary.name())).strong().color(Color32::LIGHT_BLUE), |ui| {
+                        if Self::build_boundary_menu(ui, &mut settings.boundary) {
+                            set_settings(settings);
+                        }
+                    });
+                    ui.menu_button(RichText::new(format!("Spawn Layout: {}", settings.layout.name())).strong().color(Color32::LIGHT_BLUE), |ui| {
+                        if Self::build_layout_menu(ui, &mut settings.layout) {
+                            set_settings(settings);
+                        }
+                    });
+                    ui.menu_button(RichText::new("Neighbor Search").strong().color(Color32::LIGHT_BLUE), |ui| {
+                        if ui.radio_value(&mut settings.neighbor_search, NeighborSearch::Grid, "Spatial Grid").clicked() {
+                            set_settings(settings);
+                        }
+                        if ui.radio_value(&mut settings.neighbor_search, NeighborSearch::Rapier, "Rapier Shape Queries").clicked() {
+                            set_settings(settings);
+                        }
+                    });
                     if ui.button(RichText::new("Shuffle Particles").strong().color(Color32::GREEN)).clicked() {
                         signals.shuffle_interactions = true;
                         set_signals(signals);
                     }
+                    if ui.button(RichText::new("Interaction Matrix").strong().color(Color32::GOLD)).clicked() {
+                        self.matrix_win = !self.matrix_win;
+                    }
+                    if ui.button(RichText::new("Collision Matrix").strong().color(Color32::GOLD)).clicked() {
+                        self.collision_win = !self.collision_win;
+                    }
+                    if ui.button(RichText::new("Bonding").strong().color(Color32::GOLD)).clicked() {
+                        self.bonds_win = !self.bonds_win;
+                    }
+                    if ui.button(RichText::new("Reactions").strong().color(Color32::GOLD)).clicked() {
+                        self.reactions_win = !self.reactions_win;
+                    }
+                    ui.menu_button(RichText::new(format!("Force Law: {}", settings.force_law.name())).strong().color(Color32::LIGHT_BLUE), |ui| {
+                        if Self::build_force_law_menu(ui, &mut settings.force_law) {
+                            set_settings(settings);
+                        }
+                    });
                 });
                 
                 ui.separator();
@@ -129,6 +335,24 @@ impl UI {
                     if ui.button(RichText::new("Monitor").strong().color(Color32::GOLD)).clicked() {
                         self.monitor_win = !self.monitor_win;
                     }
+                    if ui.button(RichText::new("Tools").strong().color(Color32::GOLD)).clicked() {
+                        self.tools_win = !self.tools_win;
+                    }
+                    if ui.button(RichText::new("Clusters").strong().color(Color32::GOLD)).clicked() {
+                        self.clusters_win = !self.clusters_win;
+                    }
+                    if ui.button(RichText::new("Capture Settings").strong().color(Color32::GOLD)).clicked() {
+                        self.capture_win = !self.capture_win;
+                    }
+                    let record = if recorder.is_recording() { "Stop Recording" } else { "Start Recording" };
+                    if ui.button(RichText::new(record).strong().color(Color32::RED)).clicked() {
+                        recorder.toggle();
+                        ui.close_menu();
+                    }
+                    if ui.button(RichText::new("Toggle Fullscreen (F11)").strong().color(Color32::GOLD)).clicked() {
+                        signals.toggle_fullscreen = true;
+                        set_signals(signals);
+                    }
                     if ui.button(RichText::new("Display Filled Elements").strong().color(Color32::GREEN)).clicked() {
                         let mut cfg = get_settings();
                         cfg.display = DisplayMode::ELEMENTS;
@@ -144,6 +368,16 @@ impl UI {
                         cfg.display = DisplayMode::STROKE;
                         set_settings(cfg);
                     }
+                    ui.menu_button(RichText::new(format!("Follow: {}", settings.follow.name())).strong().color(Color32::LIGHT_BLUE), |ui| {
+                        for follow in Follow::all() {
+                            if ui.radio_value(&mut settings.follow, follow, follow.name()).clicked() {
+                                set_settings(settings);
+                            }
+                        }
+                        if settings.follow == Follow::Cluster {
+                            ui.label(RichText::new("needs cluster analysis").small().italics());
+                        }
+                    });
                     if ui.button(RichText::new("Show Field Range").strong().color(Color32::BLUE)).clicked() {
                         let mut cfg = get_settings();
                         cfg.field_range = !cfg.field_range;
@@ -158,11 +392,132 @@ impl UI {
                     }
                 });
 
+                ui.separator();
+                let (play, play_color) = if running { ("⏸", Color32::YELLOW) } else { ("▶", Color32::GREEN) };
+                if ui.button(RichText::new(play).strong().color(play_color)).on_hover_text("play/pause (Space)").clicked() {
+                    signals.toggle_pause = true;
+                    set_signals(signals);
+                }
+                if ui.button(RichText::new("⏭").strong()).on_hover_text("single step (.)").clicked() {
+                    signals.single_step = true;
+                    set_signals(signals);
+                }
+                if ui.add(Slider::new(&mut settings.time_scale, 0.1..=MAX_SUBSTEPS as f32 / 2.0).logarithmic(true).max_decimals(2).text("speed")).changed() {
+                    set_settings(settings);
+                }
+
+                ui.separator();
+                ui.label(RichText::new(format!("SEED: {}", seed)).color(Color32::GRAY).monospace());
+
             })
         });
     }
 
-    fn build_monitor_win(&mut self, egui_ctx: &Context, fps: i32, fps2: i32) {
+    fn build_force_law_menu(ui: &mut Ui, force_law: &mut ForceLaw) -> bool {
+        let mut changed = false;
+        for law in ForceLaw::all() {
+            if ui.radio(force_law.same_kind(&law), law.name()).clicked() && !force_law.same_kind(&law) {
+                *force_law = law;
+                changed = true;
+            }
+        }
+        ui.separator();
+        let mut param = |ui: &mut Ui, label: &str, value: &mut f32, range: std::ops::RangeInclusive<f32>| {
+            if ui.add(Slider::new(value, range).step_by(0.01).text(label)).changed() {
+                changed = true;
+            }
+        };
+        match force_law {
+            ForceLaw::Classic => {
+                ui.label(RichText::new("uses REPEL from settings").italics());
+            },
+            ForceLaw::ParticleLife { beta } => {
+                param(ui, "beta", beta, 0.05..=0.95);
+            },
+            ForceLaw::LennardJones { sigma, epsilon } => {
+                param(ui, "sigma", sigma, 0.05..=0.9);
+                param(ui, "epsilon", epsilon, 0.01..=5.0);
+            },
+            ForceLaw::Gaussian { core, width } => {
+                param(ui, "core", core, 0.0..=0.9);
+                param(ui, "width", width, 0.01..=0.5);
+            },
+            ForceLaw::Morse { r0, alpha, depth } => {
+                param(ui, "r0", r0, 0.05..=0.95);
+                param(ui, "alpha", alpha, 0.5..=10.0);
+                param(ui, "depth", depth, 0.01..=5.0);
+            },
+        }
+        return changed;
+    }
+
+    fn build_presets_menu(&mut self, ui: &mut Ui) {
+        for preset in self.presets.iter() {
+            if ui.button(RichText::new(&preset.name).strong().color(Color32::GREEN)).on_hover_text(&preset.description).clicked() {
+                self.preset = Some(preset.clone());
+                ui.close_menu();
+            }
+        }
+        ui.separator();
+        ui.menu_button(RichText::new("My Presets").strong().color(Color32::LIGHT_BLUE), |ui| {
+            if let Some(path) = Self::files_menu(ui, PRESETS_DIR, PRESETS_EXT) {
+                self.preset_file = Some(path);
+            }
+        });
+        ui.menu_button(RichText::new("Save Current As...").strong().color(Color32::GOLD), |ui| {
+            ui.add(TextEdit::singleline(&mut self.preset_name).hint_text("preset name").desired_width(150.));
+            let name = self.preset_name.trim().to_string();
+            if ui.add_enabled(!name.is_empty(), egui::Button::new(RichText::new("SAVE").strong())).clicked() {
+                self.preset_to_save = Some(name);
+                self.preset_name.clear();
+                ui.close_menu();
+            }
+        });
+    }
+
+    /// Takes effect on the next start or restart.
+    fn build_layout_menu(ui: &mut Ui, layout: &mut SpawnLayout) -> bool {
+        let mut changed = false;
+        for mode in SpawnLayout::all() {
+            if ui.radio(layout.same_kind(&mode), mode.name()).clicked() && !layout.same_kind(&mode) {
+                *layout = mode;
+                changed = true;
+            }
+        }
+        if let SpawnLayout::Blobs { count } = layout {
+            ui.separator();
+            changed |= ui.add(Slider::new(count, 1..=20).text("blobs")).changed();
+        }
+        return changed;
+    }
+
+    fn build_boundary_menu(ui: &mut Ui, boundary: &mut Boundary) -> bool {
+        let mut changed = false;
+        for mode in Boundary::all() {
+            if ui.radio(boundary.same_kind(&mode), mode.name()).clicked() && !boundary.same_kind(&mode) {
+                *boundary = mode;
+                changed = true;
+            }
+        }
+        ui.separator();
+        match boundary {
+            Boundary::SoftCircle { stiffness } | Boundary::Box { stiffness } => {
+                changed |= ui.add(Slider::new(stiffness, 0.01..=2.0).step_by(0.01).text("stiffness")).changed();
+            },
+            Boundary::HardCircle { stiffness } => {
+                changed |= ui.add(Slider::new(stiffness, 0.0..=1.0).step_by(0.01).text("restitution")).changed();
+            },
+            Boundary::Torus => {
+                ui.label(RichText::new("forces cross the seam only with the spatial grid").italics());
+            },
+            Boundary::Open => {
+                ui.label(RichText::new("unbounded world").italics());
+            },
+        }
+        return changed;
+    }
+
+    fn build_monitor_win(&mut self, egui_ctx: &Context, fps: i32, fps2: i32, seed: u64, types: &PhysicsTypes, monitor: &mut Monitor) {
         if self.monitor_win {
             let red = 1.0 - clamp(fps2 as f32, 0.0, 60.0) / 60.0;
             let green = clamp(fps2 as f32, 0.0, 60.0) / 60.0;
@@ -177,18 +532,63 @@ impl UI {
                     //let fps = get_fps();
                     let txt = format!("dT: {} | FPS: {}({})", dt, fps, fps2);
                     ui.add(Label::new(RichText::new(txt).color(color).strong()));
+                    ui.add(Label::new(RichText::new(format!("SEED: {}", seed)).strong()));
+                    ui.separator();
+                    Self::build_monitor_plots(ui, types, monitor);
                 })
             });
         }
     }
 
+    fn build_monitor_plots(ui: &mut Ui, types: &PhysicsTypes, monitor: &mut Monitor) {
+        ui.horizontal(|row| {
+            let pause = if monitor.paused { "RESUME" } else { "PAUSE" };
+            if row.button(RichText::new(pause).strong()).clicked() {
+                monitor.paused = !monitor.paused;
+            }
+            if row.button(RichText::new("CLEAR").strong()).clicked() {
+                monitor.clear();
+            }
+            if row.button(RichText::new("EXPORT CSV").strong().color(Color32::LIGHT_BLUE)).clicked() {
+                let mut signals = signals();
+                signals.export_series = true;
+                set_signals(signals);
+            }
+        });
+        ui.add(Slider::new(&mut monitor.span, 5.0..=300.0).step_by(5.0).text("seconds"));
+        let plot = |ui: &mut Ui, id: &str, lines: Vec<Line>| {
+            Plot::new(id).height(80.0).width(280.0).include_y(0.0).legend(Legend::default())
+            .allow_drag(false).allow_zoom(false).allow_scroll(false).allow_boxed_zoom(false)
+            .show(ui, |plot_ui| {
+                for line in lines {
+                    plot_ui.line(line);
+                }
+            });
+        };
+        plot(ui, "k_eng_plot", vec![Line::new(PlotPoints::new(monitor.series(|s| s.total_k_eng as f64))).color(Color32::GOLD).name("kinetic energy")]);
+        plot(ui, "fps_plot", vec![Line::new(PlotPoints::new(monitor.series(|s| s.fps as f64))).color(Color32::GREEN).name("FPS")]);
+        plot(ui, "step_plot", vec![Line::new(PlotPoints::new(monitor.series(|s| s.step_time as f64))).color(Color32::LIGHT_RED).name("step [ms]")]);
+        let speeds = (0..monitor.types_num().min(types.types_num())).map(|t| {
+            let color = to_color32(types.get_type(t as u128).get_color());
+            Line::new(PlotPoints::new(monitor.series(|s| s.type_speeds.get(t).copied().unwrap_or(0.0) as f64))).color(color)
+        }).collect();
+        plot(ui, "speed_plot", speeds);
+        ui.label(RichText::new("mean speed per type").small());
+        if get_settings().bonds.enabled {
+            plot(ui, "bonds_plot", vec![Line::new(PlotPoints::new(monitor.series(|s| s.bonds.count as f64))).color(Color32::LIGHT_BLUE).name("bonds")]);
+            if let Some(last) = monitor.samples().back() {
+                ui.label(RichText::new(format!("bonds: {} | formed: {} | broken: {}", last.bonds.count, last.bonds.formed, last.bonds.broken)).small());
+            }
+        }
+    }
+
     fn build_settings_win(&mut self, egui_ctx: &Context) {
         if !self.settings_win {
             return;
         }
         let mut settings = get_settings();
         let w = 200.0; let h = 400.0;
-        egui::Window::new("SETTINGS").id("settings_win".into()).default_pos((SCREEN_WIDTH/2.-w/2., 0.0)).default_size(vec2(w, h))
+        egui::Window::new("SETTINGS").id("settings_win".into()).pivot(Align2::CENTER_TOP).default_pos((screen_size(egui_ctx).x/2., 0.0)).default_size(vec2(w, h))
         .title_bar(true).show(egui_ctx, |ui| {
             //ui.set_height_range(300.0..=600.0);
             //ui.set_min_width(w-50.0);
@@ -243,8 +643,21 @@ impl UI {
                 column[1].set_min_size(UIVec2::new(120., 25.));
                 let mut particle_types = settings.particle_types;
                 column[0].label(RichText::new("PARTICLE TYPES").color(Color32::LIGHT_BLUE).strong());
-                if column[1].add_sized(vec2(125., 25.), Slider::new(&mut particle_types, 1..=50)).changed() {
+                if column[1].add_sized(vec2(125., 25.), Slider::new(&mut particle_types, 1..=settings.max_types.max(1))).changed() {
                     settings.particle_types = particle_types;
+                    let mut signals = signals();
+                    signals.types_num_changed = true;
+                    set_settings(settings);
+                    set_signals(signals);
+                }
+            });
+            ui.columns(2, |column| {
+                column[0].set_max_size(UIVec2::new(60., 25.));
+                column[1].set_min_size(UIVec2::new(120., 25.));
+                let mut max_types = settings.max_types;
+                column[0].label(RichText::new("MAX TYPES").color(Color32::LIGHT_BLUE).strong());
+                if column[1].add_sized(vec2(125., 25.), DragValue::new(&mut max_types).speed(1.0).clamp_range(1..=usize::MAX)).changed() {
+                    settings.max_types = max_types;
                     set_settings(settings);
                 }
             });
@@ -291,12 +704,472 @@ impl UI {
                     set_signals(signals);
                 }
             });
+            ui.columns(2, |column| {
+                column[0].set_max_size(UIVec2::new(60., 25.));
+                column[1].set_min_size(UIVec2::new(120., 25.));
+                let mut threads = settings.threads;
+                column[0].label(RichText::new("THREADS").color(Color32::LIGHT_GRAY).strong());
+                if column[1].add_sized(vec2(125., 25.), Slider::new(&mut threads, 1..=default_threads().max(1))).changed() {
+                    settings.threads = threads;
+                    set_settings(settings);
+                }
+            });
+        });
+    }
+
+    fn build_solver_win(&mut self, egui_ctx: &Context) {
+        if !self.solver_win {
+            return;
+        }
+        let mut settings = get_settings();
+        let solver = &mut settings.solver;
+        let mut changed = false;
+        Window::new("ADVANCED PHYSICS").id("solver_win".into()).open(&mut self.solver_win).resizable(false)
+        .pivot(Align2::CENTER_TOP).default_pos((screen_size(egui_ctx).x/2., 60.)).show(egui_ctx, |ui| {
+            changed |= ui.add(Slider::new(&mut solver.solver_iterations, 1..=16).text("solver iterations")).changed();
+            changed |= ui.add(Slider::new(&mut solver.friction_iterations, 0..=8).text("friction iterations")).changed();
+            changed |= ui.add(Slider::new(&mut solver.min_island_size, 1..=256).logarithmic(true).text("min island size")).changed();
+            changed |= ui.add(Slider::new(&mut solver.prediction_distance, 0.0..=0.1).max_decimals(4).text("prediction distance")).changed();
+            changed |= ui.add(Slider::new(&mut solver.allowed_linear_error, 0.0001..=0.05).logarithmic(true).max_decimals(4).text("allowed linear error")).changed();
+            changed |= ui.add(Slider::new(&mut solver.max_ccd_substeps, 1..=8).text("max CCD substeps")).changed();
+            changed |= ui.add(Slider::new(&mut solver.gravity[0], -100.0..=100.0).text("gravity x")).changed();
+            changed |= ui.add(Slider::new(&mut solver.gravity[1], -100.0..=100.0).text("gravity y")).changed();
+            ui.separator();
+            if ui.button(RichText::new("DEFAULTS").strong()).clicked() {
+                *solver = SolverSettings::default();
+                changed = true;
+            }
+        });
+        if changed {
+            set_settings(settings);
+        }
+    }
+
+    fn build_seed_win(&mut self, egui_ctx: &Context, seed: u64) {
+        if !self.seed_win {
+            return;
+        }
+        Window::new("NEW SIM").id("seed_win".into()).resizable(false).pivot(Align2::CENTER_TOP).default_pos((screen_size(egui_ctx).x/2., screen_size(egui_ctx).y/4.))
+        .title_bar(true).show(egui_ctx, |ui| {
+            let parsed = self.seed_text.trim().parse::<u64>();
+            ui.horizontal(|row| {
+                row.label(RichText::new("SEED").color(Color32::YELLOW).strong());
+                row.add(TextEdit::singleline(&mut self.seed_text).desired_width(180.0));
+            });
+            if parsed.is_err() {
+                ui.label(RichText::new("seed must be a number").color(Color32::RED).italics());
+            }
+            ui.horizontal(|row| {
+                if row.button(RichText::new("Current").strong()).clicked() {
+                    self.seed_text = seed.to_string();
+                }
+                if row.button(RichText::new("Random").strong()).clicked() {
+                    self.seed_text = generate_seed().to_string();
+                }
+                if row.add_enabled(parsed.is_ok(), egui::Button::new(RichText::new("START").color(Color32::GREEN).strong())).clicked() {
+                    self.seed_to_start = parsed.ok();
+                    self.seed_win = false;
+                }
+                if row.button(RichText::new("CLOSE").color(Color32::RED).strong()).clicked() {
+                    self.seed_win = false;
+                }
+            });
+        });
+    }
+
+    fn build_matrix_win(&mut self, egui_ctx: &Context, types: &mut PhysicsTypes) {
+        if !self.matrix_win {
+            return;
+        }
+        let n = types.types_num();
+        let mut open = self.matrix_win;
+        Window::new("INTERACTION MATRIX").id("matrix_win".into()).open(&mut open).default_pos((10.0, 40.0))
+        .vscroll(false).resizable(true).show(egui_ctx, |ui| {
+            ui.label(RichText::new("row is acted on by column: + attract / - repel").italics());
+            ScrollArea::both().show(ui, |ui| {
+                Grid::new("matrix_grid").spacing([2.0, 2.0]).show(ui, |grid| {
+                    grid.label("");
+                    for to in 0..n {
+                        grid.vertical_centered(|col| {
+                            col.label(RichText::new("⏺").color(to_color32(types.get_type(to as u128).get_color())));
+                            col.horizontal(|buttons| {
+                                if buttons.small_button("R").on_hover_text("randomize column").clicked() {
+                                    types.fill_column(to, true);
+                                }
+                                if buttons.small_button("0").on_hover_text("zero column").clicked() {
+                                    types.fill_column(to, false);
+                                }
+                            });
+                        });
+                    }
+                    grid.end_row();
+                    for from in 0..n {
+                        grid.label(RichText::new("⏺").color(to_color32(types.get_type(from as u128).get_color())));
+                        for to in 0..n {
+                            let mut action = types.get_type(from as u128).get_action(to);
+                            grid.scope(|cell| {
+                                let fill = action_color32(action);
+                                cell.visuals_mut().widgets.inactive.bg_fill = fill;
+                                cell.visuals_mut().widgets.hovered.bg_fill = fill;
+                                cell.visuals_mut().override_text_color = Some(Color32::WHITE);
+                                let drag = DragValue::new(&mut action).speed(0.01).clamp_range(-1.0..=1.0).fixed_decimals(2);
+                                if cell.add_sized(vec2(42.0, 18.0), drag).changed() {
+                                    types.set_action(from, to, action);
+                                }
+                            });
+                        }
+                        if grid.small_button("R").on_hover_text("randomize row").clicked() {
+                            types.fill_row(from, true);
+                        }
+                        if grid.small_button("0").on_hover_text("zero row").clicked() {
+                            types.fill_row(from, false);
+                        }
+                        grid.end_row();
+                    }
+                });
+            });
+        });
+        self.matrix_win = open;
+    }
+
+    fn build_collision_win(&mut self, egui_ctx: &Context, types: &mut PhysicsTypes) {
+        if !self.collision_win {
+            return;
+        }
+        let n = types.types_num().min(COLLISION_GROUPS);
+        let mut settings = get_settings();
+        let mut open = self.collision_win;
+        Window::new("COLLISION MATRIX").id("collision_win".into()).open(&mut open).default_pos((10.0, 40.0))
+        .vscroll(false).resizable(true).show(egui_ctx, |ui| {
+            if ui.checkbox(&mut settings.collisions, "collisions enabled").changed() {
+                set_settings(settings);
+            }
+            ui.label(RichText::new("unchecked pairs pass through each other").italics());
+            if types.types_num() > COLLISION_GROUPS {
+                ui.label(RichText::new(format!("types above {} share the group of type % {}", COLLISION_GROUPS, COLLISION_GROUPS)).color(Color32::YELLOW));
+            }
+            ui.add_enabled_ui(settings.collisions, |ui| {
+                ScrollArea::both().show(ui, |ui| {
+                    Grid::new("collision_grid").spacing([2.0, 2.0]).show(ui, |grid| {
+                        grid.label("");
+                        for to in 0..n {
+                            grid.label(RichText::new("⏺").color(to_color32(types.get_type(to as u128).get_color())));
+                        }
+                        grid.end_row();
+                        for from in 0..n {
+                            grid.label(RichText::new("⏺").color(to_color32(types.get_type(from as u128).get_color())));
+                            for to in 0..n {
+                                let mut collide = types.collides(from, to);
+                                if grid.checkbox(&mut collide, "").changed() {
+                                    types.set_collides(from, to, collide);
+                                }
+                            }
+                            grid.end_row();
+                        }
+                    });
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("All").clicked() {
+                        types.collisions.iter_mut().for_each(|filter| *filter = u32::MAX);
+                    }
+                    if ui.button("None").clicked() {
+                        types.collisions.iter_mut().for_each(|filter| *filter = 0);
+                    }
+                    if ui.button("Same Type Only").clicked() {
+                        for (group, filter) in types.collisions.iter_mut().enumerate() {
+                            *filter = 1 << group;
+                        }
+                    }
+                });
+            });
+        });
+        self.collision_win = open;
+    }
+
+    fn build_bonds_win(&mut self, egui_ctx: &Context, types: &mut PhysicsTypes) {
+        if !self.bonds_win {
+            return;
+        }
+        let n = types.types_num();
+        let mut settings = get_settings();
+        let bonds = &mut settings.bonds;
+        let mut changed = false;
+        let mut open = self.bonds_win;
+        Window::new("BONDING").id("bonds_win".into()).open(&mut open).default_pos((10.0, 40.0))
+        .vscroll(false).resizable(true).show(egui_ctx, |ui| {
+            changed |= ui.checkbox(&mut bonds.enabled, "bonding enabled").changed();
+            ui.horizontal(|ui| {
+                for kind in BondKind::all() {
+                    changed |= ui.radio_value(&mut bonds.kind, kind, kind.name()).changed();
+                }
+            });
+            changed |= ui.add(Slider::new(&mut bonds.max_bonds, 1..=6).text("max bonds per particle")).changed();
+            changed |= ui.add(Slider::new(&mut bonds.break_force, 0.0..=20000.0).logarithmic(true).text("break force")).on_hover_text("0 never breaks by force").changed();
+            changed |= ui.add(Slider::new(&mut bonds.break_stretch, 1.0..=5.0).text("break stretch")).on_hover_text("multiple of the bond distance").changed();
+            ui.add_enabled_ui(bonds.kind == BondKind::Spring, |ui| {
+                changed |= ui.add(Slider::new(&mut bonds.stiffness, 0.0..=500.0).text("spring stiffness")).changed();
+                changed |= ui.add(Slider::new(&mut bonds.damping, 0.0..=10.0).text("spring damping")).changed();
+            });
+            ui.separator();
+            ui.label(RichText::new("bond distance per pair, 0 never bonds").italics());
+            ScrollArea::both().show(ui, |ui| {
+                Grid::new("bonds_grid").spacing([2.0, 2.0]).show(ui, |grid| {
+                    grid.label("");
+                    for to in 0..n {
+                        grid.label(RichText::new("⏺").color(to_color32(types.get_type(to as u128).get_color())));
+                    }
+                    grid.end_row();
+                    for from in 0..n {
+                        grid.label(RichText::new("⏺").color(to_color32(types.get_type(from as u128).get_color())));
+                        for to in 0..n {
+                            let mut distance = types.bond_distance(from, to);
+                            let drag = DragValue::new(&mut distance).speed(0.1).clamp_range(0.0..=MAX_BOND_DISTANCE).fixed_decimals(1);
+                            if grid.add_sized(vec2(42.0, 18.0), drag).changed() {
+                                types.set_bond_distance(from, to, distance);
+                            }
+                        }
+                        grid.end_row();
+                    }
+                });
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Randomize").clicked() {
+                    types.fill_bonds(true);
+                }
+                if ui.button("Clear").clicked() {
+                    types.fill_bonds(false);
+                }
+            });
+        });
+        self.bonds_win = open;
+        if changed {
+            set_settings(settings);
+        }
+    }
+
+    fn build_reactions_win(&mut self, egui_ctx: &Context, types: &mut PhysicsTypes) {
+        if !self.reactions_win {
+            return;
+        }
+        let n = types.types_num();
+        let colors: Vec<Color32> = (0..n).map(|t| to_color32(types.get_type(t as u128).get_color())).collect();
+        let mut open = self.reactions_win;
+        Window::new("REACTIONS").id("reactions_win".into()).open(&mut open).default_pos((10.0, 40.0))
+        .vscroll(false).resizable(true).show(egui_ctx, |ui| {
+            ui.label(RichText::new("A + B -> C + D with probability p per step when closer than d").italics());
+            let mut remove: Option<usize> = None;
+            ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                Grid::new("reactions_grid").spacing([4.0, 4.0]).show(ui, |grid| {
+                    for header in ["A", "", "B", "", "C", "", "D", "p", "d", "count", ""] {
+                        grid.label(RichText::new(header).strong());
+                    }
+                    grid.end_row();
+                    for (i, reaction) in types.reactions.iter_mut().enumerate() {
+                        Self::type_combo(grid, ("reaction_a", i), &colors, &mut reaction.a);
+                        grid.label("+");
+                        Self::type_combo(grid, ("reaction_b", i), &colors, &mut reaction.b);
+                        grid.label("->");
+                        Self::type_combo(grid, ("reaction_c", i), &colors, &mut reaction.into_a);
+                        grid.label("+");
+                        Self::type_combo(grid, ("reaction_d", i), &colors, &mut reaction.into_b);
+                        grid.add(DragValue::new(&mut reaction.probability).speed(0.001).clamp_range(0.0..=1.0).fixed_decimals(3));
+                        grid.add(DragValue::new(&mut reaction.distance).speed(0.1).clamp_range(0.0..=MAX_REACTION_DISTANCE).fixed_decimals(1));
+                        grid.label(reaction.count.to_string());
+                        if grid.small_button("✖").on_hover_text("remove reaction").clicked() {
+                            remove = Some(i);
+                        }
+                        grid.end_row();
+                    }
+                });
+            });
+            if let Some(i) = remove {
+                types.reactions.remove(i);
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Add").clicked() {
+                    types.reactions.push(Reaction::catalysis(0, 1 % n, 1 % n));
+                }
+                if ui.button("Reset Counts").clicked() {
+                    types.reactions.iter_mut().for_each(|reaction| reaction.count = 0);
+                }
+                if ui.button("Clear").clicked() {
+                    types.reactions.clear();
+                }
+            });
+        });
+        self.reactions_win = open;
+    }
+
+    fn type_combo(ui: &mut Ui, id: impl std::hash::Hash, colors: &[Color32], value: &mut usize) {
+        let label = |t: usize| RichText::new(format!("⏺ {}", t)).color(colors.get(t).copied().unwrap_or(Color32::WHITE));
+        ComboBox::from_id_source(id).width(48.0).selected_text(label(*value)).show_ui(ui, |ui| {
+            for t in 0..colors.len() {
+                ui.selectable_value(value, t, label(t));
+            }
+        });
+    }
+
+    fn build_inspector_win(&mut self, egui_ctx: &Context, types: &PhysicsTypes, selected: Option<&ElementInfo>) {
+        let info = match selected {
+            Some(info) => info,
+            None => return,
+        };
+        let mut open = true;
+        Window::new("INSPECTOR").id("inspector_win".into()).open(&mut open).resizable(false)
+        .anchor(Align2::LEFT_BOTTOM, [5.0, -5.0]).show(egui_ctx, |ui| {
+            Grid::new("inspector_grid").num_columns(2).striped(true).show(ui, |grid| {
+                grid.label(RichText::new("KEY").strong());
+                grid.label(RichText::new(format!("{:016x}", info.key)).monospace());
+                grid.end_row();
+                grid.label(RichText::new("TYPE").strong());
+                grid.label(RichText::new(format!("⏺ {}", info.physics_type)).color(to_color32(info.color)).strong());
+                grid.end_row();
+                grid.label(RichText::new("POSITION").strong());
+                grid.label(format!("[{:.1}, {:.1}]", info.pos.x, info.pos.y));
+                grid.end_row();
+                grid.label(RichText::new("VELOCITY").strong());
+                grid.label(format!("[{:.1}, {:.1}] | {:.1}", info.vel.x, info.vel.y, info.vel.length()));
+                grid.end_row();
+                grid.label(RichText::new("KINETIC ENERGY").strong());
+                grid.label(format!("{:.2}", info.kin_eng));
+                grid.end_row();
+                grid.label(RichText::new("MASS").strong());
+                grid.label(format!("{:.3}", info.mass));
+                grid.end_row();
+                grid.label(RichText::new("NEIGHBORS").strong());
+                grid.label(format!("{}", info.neighbors));
+                grid.end_row();
+            });
+            ui.separator();
+            ui.label(RichText::new("INTERACTIONS").strong());
+            ui.horizontal_wrapped(|row| {
+                for (to, action) in info.actions.iter().enumerate() {
+                    let color = to_color32(types.get_type(to as u128).get_color());
+                    row.label(RichText::new("⏺").color(color));
+                    row.label(RichText::new(format!("{:+.2}", action)).background_color(action_color32(*action)).color(Color32::WHITE).monospace());
+                }
+            });
+        });
+        if !open {
+            self.deselect = true;
+        }
+    }
+
+    fn build_tools_win(&mut self, egui_ctx: &Context, types: &PhysicsTypes, brush: &mut Brush) {
+        if !self.tools_win {
+            return;
+        }
+        let mut open = self.tools_win;
+        Window::new("TOOLS").id("tools_win".into()).open(&mut open).resizable(false)
+        .anchor(Align2::LEFT_TOP, [5.0, 30.0]).show(egui_ctx, |ui| {
+            ui.horizontal_wrapped(|row| {
+                for tool in BrushTool::all() {
+                    row.selectable_value(&mut brush.tool, tool, RichText::new(tool.name()).color(to_color32(tool.color())).strong());
+                }
+            });
+            ui.separator();
+            ui.add(Slider::new(&mut brush.radius, 5.0..=500.0).step_by(1.0).text("radius"));
+            ui.add(Slider::new(&mut brush.strength, 1.0..=100.0).step_by(1.0).text("strength"));
+            let max_type = types.types_num().saturating_sub(1);
+            brush.spawn_type = brush.spawn_type.min(max_type);
+            ui.horizontal(|row| {
+                row.label("spawn type");
+                row.add(DragValue::new(&mut brush.spawn_type).clamp_range(0..=max_type));
+                row.label(RichText::new("⏺").color(to_color32(types.get_type(brush.spawn_type as u128).get_color())));
+            });
+        });
+        self.tools_win = open;
+        if !self.tools_win {
+            brush.tool = BrushTool::Select;
+        }
+    }
+
+    fn build_capture_win(&mut self, egui_ctx: &Context, recorder: &mut Recorder) {
+        if !self.capture_win {
+            return;
+        }
+        Window::new("CAPTURE").id("capture_win".into()).open(&mut self.capture_win).resizable(false)
+        .pivot(Align2::RIGHT_TOP).default_pos((screen_size(egui_ctx).x-5., 30.)).show(egui_ctx, |ui| {
+            ui.add_enabled_ui(!recorder.is_recording(), |ui| {
+                ui.horizontal(|row| {
+                    for format in CaptureFormat::all() {
+                        row.radio_value(&mut recorder.format, format, format.name());
+                    }
+                });
+                ui.add(Slider::new(&mut recorder.every, 1..=30).text("every Nth frame"));
+                ui.horizontal(|row| {
+                    row.label("folder");
+                    row.add(TextEdit::singleline(&mut recorder.dir).desired_width(150.));
+                });
+            });
+            ui.separator();
+            if recorder.is_recording() {
+                ui.label(RichText::new(format!("⏺ {} frames", recorder.saved_frames())).color(Color32::RED).strong());
+                ui.label(RichText::new(recorder.output().display().to_string()).small());
+            }
+            if let Some(e) = &recorder.last_error {
+                ui.label(RichText::new(e).color(Color32::RED).small());
+            }
+            let record = if recorder.is_recording() { "STOP" } else { "START" };
+            if ui.button(RichText::new(record).strong().color(Color32::RED)).clicked() {
+                recorder.toggle();
+            }
+        });
+    }
+
+    fn build_clusters_win(&mut self, egui_ctx: &Context, types: &PhysicsTypes, clusters: &mut ClusterAnalysis) {
+        if !self.clusters_win {
+            return;
+        }
+        const BUCKETS: [usize; 7] = [1, 5, 10, 20, 50, 100, 500];
+        Window::new("CLUSTERS").id("clusters_win".into()).open(&mut self.clusters_win).resizable(false)
+        .pivot(Align2::RIGHT_TOP).default_pos((screen_size(egui_ctx).x-5., 200.)).show(egui_ctx, |ui| {
+            ui.checkbox(&mut clusters.enabled, "analyze");
+            ui.checkbox(&mut clusters.color_by_cluster, "color by cluster");
+            ui.add(Slider::new(&mut clusters.distance, 1.0..=200.0).step_by(1.0).text("link distance"));
+            ui.add(Slider::new(&mut clusters.every, 1..=300).text("every N steps"));
+            ui.add(Slider::new(&mut clusters.min_size, 1..=100).text("min size"));
+            ui.separator();
+            let report = &clusters.report;
+            ui.label(RichText::new(format!("clusters: {} | loose: {}", report.clusters.len(), report.loose)).strong());
+            ui.label(format!("largest: {} | mean size: {:.1}", report.largest(), report.mean_size()));
+            ui.label(RichText::new(format!("at {:.1}s", report.sim_time)).small());
+            ui.collapsing("size distribution", |ui| {
+                Grid::new("cluster_sizes").striped(true).show(ui, |grid| {
+                    for (i, count) in report.size_histogram(&BUCKETS).iter().enumerate() {
+                        let label = match BUCKETS.get(i+1) {
+                            Some(next) => format!("{}-{}", BUCKETS[i], next-1),
+                            None => format!("{}+", BUCKETS[i]),
+                        };
+                        grid.label(label);
+                        grid.label(count.to_string());
+                        grid.end_row();
+                    }
+                });
+            });
+            ui.collapsing("largest clusters", |ui| {
+                ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                    Grid::new("cluster_composition").striped(true).show(ui, |grid| {
+                        for cluster in report.clusters.iter().take(20) {
+                            grid.label(RichText::new(format!("#{}", cluster.id)).color(to_color32(cluster_color(cluster.id))).strong());
+                            grid.label(cluster.size.to_string());
+                            grid.horizontal(|row| {
+                                for (t, n) in cluster.composition.iter().enumerate().filter(|(t, n)| **n > 0 && *t < types.types_num()) {
+                                    let color = to_color32(types.get_type(t as u128).get_color());
+                                    row.label(RichText::new(n.to_string()).color(color));
+                                }
+                            });
+                            grid.end_row();
+                        }
+                    });
+                });
+            });
         });
     }
 
     fn build_about_win(&mut self, egui_ctx: &Context) {
         if self.about_win {
-            Window::new("ABOUT").resizable(false).default_pos((SCREEN_WIDTH/2.-150., SCREEN_HEIGHT/6.)).min_height(680.).min_width(120.)
+            Window::new("ABOUT").resizable(false).pivot(Align2::CENTER_TOP).default_pos((screen_size(egui_ctx).x/2., screen_size(egui_ctx).y/6.)).min_height(680.).min_width(120.)
             .title_bar(true).show(egui_ctx, |ui| {
                 let big_logo = self.big_logo.clone().unwrap();
                 ui.vertical_centered(|pic| {
@@ -325,6 +1198,26 @@ impl UI {
 
 }
 
+/// Current size of the window in UI points, windows are placed relative to it.
+fn screen_size(egui_ctx: &Context) -> UIVec2 {
+    return egui_ctx.screen_rect().size();
+}
+
+fn to_color32(color: macroquad::color::Color) -> Color32 {
+    let [r, g, b, a] = [color.r, color.g, color.b, color.a].map(|c| (c * 255.0) as u8);
+    return Color32::from_rgba_unmultiplied(r, g, b, a);
+}
+
+/// Green for attraction, red for repulsion, brightness follows strength.
+fn action_color32(action: f32) -> Color32 {
+    let v = (clamp(action.abs(), 0.0, 1.0) * 200.0) as u8;
+    return if action >= 0.0 {
+        Color32::from_rgb(20, 20 + v, 20)
+    } else {
+        Color32::from_rgb(20 + v, 20, 20)
+    };
+}
+
 struct LogoImage {
     texture: Option<TextureHandle>,
 }
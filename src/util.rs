@@ -2,6 +2,8 @@
 
 use std::time::{UNIX_EPOCH, Duration};
 use std::f32::consts::PI;
+use std::fs;
+use std::path::{Path, PathBuf};
 use crate::globals::*;
 use macroquad::{color, prelude::*};
 use rapier2d::na::{*, clamp};
@@ -73,21 +75,18 @@ pub fn angle2vec2(angle: f32) -> Vec2 {
     return v;
 }
 
-/* pub fn wrap_around(v: &Vec2) -> Vec2 {
-    let tolerance = 5.0;
-    let mut vr = Vec2::new(v.x, v.y);
-    if vr.x > WORLD_W + tolerance {
-        vr.x = 0.0 - tolerance;
-    } else if vr.x < 0.0 - tolerance {
-        vr.x = WORLD_W + tolerance;
-    }
-    if vr.y > WORLD_H + tolerance {
-        vr.y = 0.0 - tolerance;
-    } else if vr.y < 0.0 - tolerance {
-        vr.y = WORLD_H + tolerance;
-    }
-    return vr;
-} */
+/// Wraps a position into the square `[-size/2, size/2)` centered at the origin.
+pub fn wrap_around(v: Vec2, size: f32) -> Vec2 {
+    let half = size / 2.0;
+    let wrap = |x: f32| (x + half).rem_euclid(size) - half;
+    return Vec2::new(wrap(v.x), wrap(v.y));
+}
+
+/// Shortest displacement from `a` to `b` in a periodic square world of side `size`.
+pub fn periodic_delta(a: Vec2, b: Vec2, size: f32) -> Vec2 {
+    let d = b - a;
+    return d - (d / size).round() * size;
+}
 
 pub fn make_isometry(posx: f32, posy: f32, rotation: f32) -> Isometry2<f32> {
     let iso = Isometry2::new(Vector2::new(posx, posy), rotation);
@@ -137,10 +136,23 @@ pub fn vec2_to_point2_collection(vec2_list: &Vec<Vec2>) -> Vec<Point2<f32>> {
 }
 
 pub fn generate_seed() -> u64 {
-    let t0 = UNIX_EPOCH.elapsed().unwrap().as_secs();
-    let tx = (t0%100).pow(2);
-    let t1 = (t0 as f32).sqrt() as u64;
-    return t1*tx;
+    let t0 = UNIX_EPOCH.elapsed().unwrap().as_nanos() as u64;
+    return splitmix64(t0);
+}
+
+/// One round of splitmix64, spreads close inputs (e.g. consecutive timestamps) over the whole u64 range.
+pub fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    return z ^ (z >> 31);
+}
+
+/// Full 64-bit key drawn from the seeded macroquad RNG stream.
+pub fn random_key() -> u64 {
+    let hi = rand::rand() as u64;
+    let lo = rand::rand() as u64;
+    return (hi << 32) | lo;
 }
 
 pub fn draw_smooth_circle(r: f32, center: Vec2, detail: f32, width: f32, color: Color) {
@@ -157,4 +169,63 @@ pub fn draw_smooth_circle(r: f32, center: Vec2, detail: f32, width: f32, color:
     let p0 = center + Vec2::from_angle(angle) * r;
     let p1 = center + Vec2::from_angle(0.0) * r;
     draw_line(p0.x, p0.y, p1.x, p1.y, width, color);
-}
\ No newline at end of file
+}
+
+pub fn timestamped_path(dir: &str, prefix: &str, ext: &str) -> PathBuf {
+    let t = UNIX_EPOCH.elapsed().unwrap().as_millis();
+    return Path::new(dir).join(format!("{}_{}.{}", prefix, t, ext));
+}
+
+pub fn list_files(dir: &str, ext: &str) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|entry| entry.ok()).map(|entry| entry.path())
+            .filter(|path| path.is_file() && path.extension().is_some_and(|e| e == ext))
+            .collect(),
+        Err(_) => vec![],
+    };
+    files.sort();
+    return files;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Vec2, b: Vec2) -> bool {
+        return a.distance(b) < 1e-3;
+    }
+
+    #[test]
+    fn wrap_around_keeps_points_inside() {
+        let size = 100.0;
+        assert_eq!(wrap_around(Vec2::new(10.0, -20.0), size), Vec2::new(10.0, -20.0));
+        assert!(close(wrap_around(Vec2::new(55.0, 0.0), size), Vec2::new(-45.0, 0.0)));
+        assert!(close(wrap_around(Vec2::new(0.0, -51.0), size), Vec2::new(0.0, 49.0)));
+        assert!(close(wrap_around(Vec2::new(260.0, -310.0), size), Vec2::new(-40.0, -10.0)));
+        for i in -50..50 {
+            let p = wrap_around(Vec2::new(i as f32 * 7.3, i as f32 * -11.1), size);
+            assert!(p.x >= -50.0 && p.x < 50.0 && p.y >= -50.0 && p.y < 50.0, "{:?}", p);
+        }
+    }
+
+    #[test]
+    fn periodic_delta_takes_the_short_way_across_the_seam() {
+        let size = 100.0;
+        let a = Vec2::new(48.0, -49.0);
+        let b = Vec2::new(-48.0, 49.0);
+        assert!(close(periodic_delta(a, b, size), Vec2::new(4.0, -2.0)));
+        assert!(close(periodic_delta(b, a, size), Vec2::new(-4.0, 2.0)));
+        assert!(close(periodic_delta(Vec2::new(-10.0, 5.0), Vec2::new(20.0, -15.0), size), Vec2::new(30.0, -20.0)));
+    }
+
+    #[test]
+    fn periodic_delta_ignores_which_copy_of_the_point_is_used() {
+        let size = 100.0;
+        let a = Vec2::new(12.0, -30.0);
+        let b = Vec2::new(-40.0, 45.0);
+        let d = periodic_delta(a, b, size);
+        assert!(d.x.abs() <= 50.0 && d.y.abs() <= 50.0);
+        assert!(close(periodic_delta(a, b + Vec2::new(size, -2.0 * size), size), d));
+        assert!(close(periodic_delta(wrap_around(a + Vec2::splat(3.0 * size), size), b, size), d));
+    }
+}